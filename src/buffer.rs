@@ -1,3 +1,9 @@
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::Path;
+
+use crate::Result;
+
 pub type Rgb = [f32; 3];
 
 pub trait PixelType: Copy {
@@ -120,3 +126,23 @@ impl<P: PixelType> Buffer<P> {
         })
     }
 }
+
+impl Buffer<[u8; 3]> {
+    /// Writes the buffer to a png file, turned the way the display shows it.
+    pub fn save_png<Q: AsRef<Path>>(&self, path: Q) -> Result<()> {
+        let (width, height) = (self.height, self.width);
+        let mut data = vec![0; (width * height * 3) as usize];
+        for (x, y, rgb) in self.pixels() {
+            let i = ((x * width + (width - 1 - y)) * 3) as usize;
+            data[i..i + 3].copy_from_slice(&rgb);
+        }
+
+        let file = BufWriter::new(File::create(path)?);
+        let mut encoder = png::Encoder::new(file, width, height);
+        encoder.set_color(png::ColorType::RGB);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder.write_header()?.write_image_data(&data)?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,125 @@
+use chrono::{Datelike, Duration, NaiveDate, Weekday};
+
+/// Which days something applies to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Days {
+    /// Monday to Friday, unless it's a public holiday
+    Weekdays,
+    Weekends,
+    Holidays,
+    Day(Weekday),
+}
+
+impl Days {
+    pub fn contains(self, date: NaiveDate) -> bool {
+        let weekend = matches!(date.weekday(), Weekday::Sat | Weekday::Sun);
+        match self {
+            Days::Weekdays => !weekend && !is_public_holiday(date),
+            Days::Weekends => weekend,
+            Days::Holidays => is_public_holiday(date),
+            Days::Day(weekday) => date.weekday() == weekday,
+        }
+    }
+}
+
+impl std::str::FromStr for Days {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "weekdays" => Ok(Days::Weekdays),
+            "weekends" => Ok(Days::Weekends),
+            "holidays" => Ok(Days::Holidays),
+            day => day.parse().map(Days::Day).map_err(|_| {
+                format!("expected weekdays, weekends, holidays or a day such as mon: {}", s)
+            }),
+        }
+    }
+}
+
+/// Whether `date` is a public holiday in Sweden, or one of the eves that
+/// traffic runs as if it were.
+pub fn is_public_holiday(date: NaiveDate) -> bool {
+    let year = date.year();
+    let easter = easter_sunday(year);
+    // ex: midsummer day is the Saturday between the 20th and 26th of June
+    let saturday_from = |month, day| {
+        let first = NaiveDate::from_ymd(year, month, day);
+        let days_until = (7 + Weekday::Sat.num_days_from_monday()
+            - first.weekday().num_days_from_monday())
+            % 7;
+        first + Duration::days(days_until.into())
+    };
+    let midsummer_day = saturday_from(6, 20);
+
+    let holidays = [
+        NaiveDate::from_ymd(year, 1, 1),
+        NaiveDate::from_ymd(year, 1, 6),
+        easter - Duration::days(2),
+        easter,
+        easter + Duration::days(1),
+        NaiveDate::from_ymd(year, 5, 1),
+        // Ascension day
+        easter + Duration::days(39),
+        // Pentecost
+        easter + Duration::days(49),
+        NaiveDate::from_ymd(year, 6, 6),
+        midsummer_day - Duration::days(1),
+        midsummer_day,
+        // All saints' day
+        saturday_from(10, 31),
+        NaiveDate::from_ymd(year, 12, 24),
+        NaiveDate::from_ymd(year, 12, 25),
+        NaiveDate::from_ymd(year, 12, 26),
+        NaiveDate::from_ymd(year, 12, 31),
+    ];
+
+    holidays.contains(&date)
+}
+
+/// Easter Sunday in the Gregorian calendar, by the anonymous algorithm
+/// published in Nature in 1876.
+fn easter_sunday(year: i32) -> NaiveDate {
+    let a = year % 19;
+    let b = year / 100;
+    let c = year % 100;
+    let d = b / 4;
+    let e = b % 4;
+    let f = (b + 8) / 25;
+    let g = (b - f + 1) / 3;
+    let h = (19 * a + b - d - g + 15) % 30;
+    let i = c / 4;
+    let k = c % 4;
+    let l = (32 + 2 * e + 2 * i - h - k) % 7;
+    let m = (a + 11 * h + 22 * l) / 451;
+    let month = (h + l - 7 * m + 114) / 31;
+    let day = (h + l - 7 * m + 114) % 31 + 1;
+
+    NaiveDate::from_ymd(year, month as u32, day as u32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_movable_holidays() {
+        assert_eq!(easter_sunday(2024), NaiveDate::from_ymd(2024, 3, 31));
+        assert_eq!(easter_sunday(2025), NaiveDate::from_ymd(2025, 4, 20));
+
+        // Good Friday, Ascension day, midsummer eve and All saints' day
+        for date in &["2024-03-29", "2024-05-09", "2024-06-21", "2024-11-02"] {
+            assert!(is_public_holiday(date.parse().unwrap()), "{}", date);
+        }
+        assert!(!is_public_holiday("2024-06-20".parse().unwrap()));
+    }
+
+    #[test]
+    fn weekdays_leave_out_holidays() {
+        // A Wednesday, and the Wednesday that's Christmas day
+        assert!(Days::Weekdays.contains("2024-12-18".parse().unwrap()));
+        assert!(!Days::Weekdays.contains("2024-12-25".parse().unwrap()));
+        assert!(Days::Holidays.contains("2024-12-25".parse().unwrap()));
+        assert_eq!("Tue".parse::<Days>(), Ok(Days::Day(Weekday::Tue)));
+    }
+}
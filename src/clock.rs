@@ -0,0 +1,57 @@
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Utc};
+
+/// Where the board gets the time from. Replays run on a simulated clock
+/// that starts when the recording does, so that the departures count down
+/// the way they did back then, and stops when it ends, so that the last
+/// board stays up.
+#[derive(Debug, Clone, Copy)]
+pub enum Clock {
+    System,
+    Simulated {
+        /// What the clock showed at `started`
+        start: DateTime<Utc>,
+        /// Where the clock stops
+        end: DateTime<Utc>,
+        started: Instant,
+        /// How many times faster than real time the clock runs
+        speed: f64,
+    },
+}
+
+impl Clock {
+    pub fn simulated(start: DateTime<Utc>, end: DateTime<Utc>, speed: f64) -> Clock {
+        Clock::Simulated {
+            start,
+            end,
+            started: Instant::now(),
+            speed,
+        }
+    }
+
+    pub fn now(&self) -> DateTime<Utc> {
+        match *self {
+            Clock::System => Utc::now(),
+            Clock::Simulated {
+                start,
+                end,
+                started,
+                speed,
+            } => {
+                let elapsed = chrono::Duration::from_std(started.elapsed().mul_f64(speed));
+                end.min(start + elapsed.unwrap_or_else(|_| chrono::Duration::zero()))
+            }
+        }
+    }
+
+    /// How long to wait, in real time, for the clock to reach `time`. Zero
+    /// if it already has.
+    pub fn until(&self, time: DateTime<Utc>) -> Duration {
+        let left = (time - self.now()).to_std().unwrap_or_default();
+        match *self {
+            Clock::System => left,
+            Clock::Simulated { speed, .. } => left.div_f64(speed),
+        }
+    }
+}
@@ -0,0 +1,576 @@
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::path::Path;
+use std::str::FromStr;
+
+use chrono::{DateTime, NaiveDateTime, Utc};
+use chrono_tz::Tz;
+use serde::{de, Deserialize, Deserializer};
+use serde_derive::Deserialize;
+
+use crate::{
+    buffer::Rgb,
+    calendar::Days,
+    departure::TransportMode,
+    filter::Rule,
+    hardware::HardwareConfig,
+    reachability::Reachability,
+    schedule::TimeRange,
+    source::{Site, SourceConfig},
+    Result,
+};
+
+/// Everything that can be set in the config file. Values that have a
+/// command line flag are written the same way in both places, and the flag
+/// wins when both are given.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// sl, sl-transport, gtfs-rt, siri or fixture
+    pub source: String,
+    /// Trafiklab key for the sl source
+    pub api_key: Option<String>,
+    pub stations: Vec<Station>,
+    pub gtfs: GtfsConfig,
+    /// SIRI StopMonitoring service, URL or file
+    pub siri_feed: Option<String>,
+    /// Recorded response for the fixture source
+    pub fixture: String,
+    #[serde(deserialize_with = "parsed")]
+    pub timezone: Tz,
+    /// Where the last successful response is kept
+    pub state_file: String,
+    /// Seconds after which realtime data is replaced by the timetable
+    pub fallback_after: i64,
+    pub polling: PollingConfig,
+    pub filters: FilterConfig,
+    /// Minutes it takes to walk to the departures
+    pub walk_time: u32,
+    /// Minutes, by stop point id or designation
+    pub stop_walk_times: HashMap<String, u32>,
+    pub delays: DelayConfig,
+    pub layout: Layout,
+    pub theme: Theme,
+    pub hardware: HardwareConfig,
+    /// Changes to the above at certain times, the first that applies wins
+    pub profiles: Vec<Profile>,
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            source: "sl".to_string(),
+            api_key: None,
+            stations: vec![],
+            gtfs: GtfsConfig::default(),
+            siri_feed: None,
+            fixture: "./test/data/sl.json".to_string(),
+            timezone: chrono_tz::Europe::Stockholm,
+            state_file: "last_response.json".to_string(),
+            fallback_after: 300,
+            polling: PollingConfig::default(),
+            filters: FilterConfig::default(),
+            walk_time: 0,
+            stop_walk_times: HashMap::new(),
+            delays: DelayConfig::default(),
+            layout: Layout::default(),
+            theme: Theme::default(),
+            hardware: HardwareConfig::default(),
+            profiles: vec![],
+        }
+    }
+}
+
+impl Config {
+    /// Reads the config file at `path`. Errors name the offending key.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Config> {
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(path)?;
+        toml::from_str(&text).map_err(|e| format!("{}: {}", path.display(), e).into())
+    }
+
+    /// Checks that the board can be set up, with each of the profiles.
+    pub fn validate(&self) -> Result<()> {
+        self.validate_settings()?;
+        self.source_config()?;
+        for (index, profile) in self.profiles.iter().enumerate() {
+            if let Err(e) = self.with_profile(Some(index)).source_config() {
+                return Err(format!("profile {}: {}", profile.name, e).into());
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Checks the settings that don't depend on the source, which would
+    /// otherwise only turn out to be wrong once the board is running.
+    pub fn validate_settings(&self) -> Result<()> {
+        self.polling.validate()?;
+        self.layout.validate()?;
+        for profile in &self.profiles {
+            if let Some(layout) = &profile.layout {
+                if let Err(e) = layout.validate() {
+                    return Err(format!("profile {}: {}", profile.name, e).into());
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The index of the profile that applies at `now`, if any.
+    pub fn profile_at(&self, now: DateTime<Utc>) -> Option<usize> {
+        let now = now.with_timezone(&self.timezone).naive_local();
+        self.profiles.iter().position(|profile| profile.applies(now))
+    }
+
+    /// This config with the settings from the profile at `index` in place.
+    pub fn with_profile(&self, index: Option<usize>) -> Config {
+        let mut config = self.clone();
+        let profile = match index.and_then(|index| self.profiles.get(index)) {
+            Some(profile) => profile,
+            None => return config,
+        };
+
+        if let Some(stations) = &profile.stations {
+            config.stations = stations.clone();
+        }
+        if let Some(filters) = &profile.filters {
+            config.filters = filters.clone();
+        }
+        if let Some(layout) = profile.layout {
+            config.layout = layout;
+        }
+        config
+    }
+
+    /// Works out which source to use, or explains what's missing.
+    pub fn source_config(&self) -> Result<SourceConfig> {
+        match self.source.as_str() {
+            "fixture" => Ok(SourceConfig::Fixture {
+                path: self.fixture.clone().into(),
+            }),
+            "sl" => {
+                let api_key = match &self.api_key {
+                    Some(api_key) => api_key,
+                    None => return Err("api_key: required by the sl source".into()),
+                };
+                station_source(&self.stations, |site_id| SourceConfig::SlV4 {
+                    api_key: api_key.clone(),
+                    site_id,
+                })
+                .ok_or_else(|| "stations: the sl source needs at least one".into())
+            }
+            "sl-transport" => {
+                station_source(&self.stations, |site_id| SourceConfig::SlTransport { site_id })
+                    .ok_or_else(|| "stations: the sl-transport source needs at least one".into())
+            }
+            "gtfs-rt" => match &self.gtfs {
+                GtfsConfig {
+                    realtime_feed: None,
+                    ..
+                } => Err("gtfs.realtime_feed: required by the gtfs-rt source".into()),
+                GtfsConfig {
+                    static_path: None, ..
+                } => Err("gtfs.static: required by the gtfs-rt source".into()),
+                GtfsConfig { stops, .. } if stops.is_empty() => {
+                    Err("gtfs.stops: the gtfs-rt source needs at least one".into())
+                }
+                GtfsConfig {
+                    realtime_feed: Some(feed),
+                    static_path: Some(static_path),
+                    stops,
+                } => Ok(SourceConfig::GtfsRealtime {
+                    feed: feed.clone(),
+                    static_path: static_path.into(),
+                    stop_ids: stops.clone(),
+                }),
+            },
+            "siri" => match &self.siri_feed {
+                Some(feed) => Ok(SourceConfig::Siri { feed: feed.clone() }),
+                None => Err("siri_feed: required by the siri source".into()),
+            },
+            other => Err(format!("source: unknown source {}", other).into()),
+        }
+    }
+}
+
+/// Wraps the source for each station in a merged board, unless there's
+/// just the one and no walk to account for.
+fn station_source<F>(stations: &[Station], source: F) -> Option<SourceConfig>
+where
+    F: Fn(u32) -> SourceConfig,
+{
+    match stations {
+        [] => None,
+        [Station { id, walk_time: 0 }] => Some(source(*id)),
+        _ => Some(SourceConfig::Merged {
+            sites: stations
+                .iter()
+                .map(|station| Site {
+                    source: source(station.id),
+                    walk_time: station.walk_time,
+                })
+                .collect(),
+        }),
+    }
+}
+
+/// A station to show departures from.
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Station {
+    pub id: u32,
+    /// Minutes it takes to walk to the station
+    #[serde(default)]
+    pub walk_time: u32,
+}
+
+impl FromStr for Station {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let invalid = |_| format!("invalid station, expected <id> or <id>:<walk minutes>: {}", s);
+        let (id, walk_time) = match s.find(':') {
+            Some(pos) => (&s[..pos], s[pos + 1..].parse().map_err(invalid)?),
+            None => (s, 0),
+        };
+
+        Ok(Station {
+            id: id.parse().map_err(invalid)?,
+            walk_time,
+        })
+    }
+}
+
+/// Settings for certain days and hours. Sections given here replace those of
+/// the main config as a whole.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Profile {
+    /// Shown in the header while the profile applies, ex: Morning
+    pub name: String,
+    /// weekdays, weekends, holidays or single days such as mon. Defaults to
+    /// every day
+    #[serde(default, deserialize_with = "parsed_list")]
+    pub days: Vec<Days>,
+    /// ex: 06:00-09:30, defaults to all day
+    #[serde(default, deserialize_with = "parsed_list")]
+    pub hours: Vec<TimeRange>,
+    pub stations: Option<Vec<Station>>,
+    pub filters: Option<FilterConfig>,
+    pub layout: Option<Layout>,
+}
+
+impl Profile {
+    /// Whether the profile applies at the local time `now`.
+    fn applies(&self, now: NaiveDateTime) -> bool {
+        let day = self.days.is_empty() || self.days.iter().any(|days| days.contains(now.date()));
+        let hour = self.hours.is_empty() || self.hours.iter().any(|hours| hours.contains(now.time()));
+        day && hour
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct GtfsConfig {
+    /// TripUpdates feed, URL or file
+    pub realtime_feed: Option<String>,
+    /// Static GTFS zip, also used for the timetable fallback
+    #[serde(rename = "static")]
+    pub static_path: Option<String>,
+    /// stop_ids to show departures from
+    pub stops: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PollingConfig {
+    /// Seconds between refreshes during commute hours
+    pub refresh_interval: u64,
+    /// Seconds between refreshes outside commute hours
+    pub idle_interval: u64,
+    #[serde(deserialize_with = "parsed_list")]
+    pub commute: Vec<TimeRange>,
+    /// When the display sleeps
+    #[serde(deserialize_with = "parsed_list")]
+    pub night: Vec<TimeRange>,
+    pub monthly_budget: Option<u32>,
+    pub budget_file: String,
+}
+
+impl PollingConfig {
+    fn validate(&self) -> std::result::Result<(), String> {
+        above_zero("polling.refresh_interval", self.refresh_interval as f32)?;
+        above_zero("polling.idle_interval", self.idle_interval as f32)
+    }
+}
+
+impl Default for PollingConfig {
+    fn default() -> PollingConfig {
+        PollingConfig {
+            refresh_interval: 60,
+            idle_interval: 300,
+            commute: vec![],
+            night: vec![],
+            monthly_budget: None,
+            budget_file: "api_usage.json".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FilterConfig {
+    /// Defaults to all of them
+    #[serde(deserialize_with = "parsed_list")]
+    pub modes: Vec<TransportMode>,
+    #[serde(deserialize_with = "parsed_list")]
+    pub include: Vec<Rule>,
+    #[serde(deserialize_with = "parsed_list")]
+    pub exclude: Vec<Rule>,
+}
+
+impl FilterConfig {
+    /// The modes to show, in the order the button cycles through them.
+    pub fn visible_modes(&self) -> Vec<TransportMode> {
+        if self.modes.is_empty() {
+            TransportMode::ALL.to_vec()
+        } else {
+            self.modes.clone()
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DelayConfig {
+    /// Minutes a departure must differ from the timetable by to be shown
+    pub threshold: i64,
+    /// Minutes of delay from which it counts as severe
+    pub severe: i64,
+}
+
+impl Default for DelayConfig {
+    fn default() -> DelayConfig {
+        DelayConfig {
+            threshold: 2,
+            severe: 10,
+        }
+    }
+}
+
+/// Sizes of the text and columns on the board, in pixels.
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Layout {
+    pub font_size: f32,
+    /// For alerts, notes and other secondary text
+    pub small_font_size: f32,
+    pub row_height: u32,
+    pub header_height: u32,
+    pub alert_height: u32,
+    pub clock_width: u32,
+    pub mode_width: u32,
+    pub line_width: u32,
+    /// Only shown when the board has several sites
+    pub site_width: u32,
+    pub time_width: u32,
+    /// For the delay and the struck through scheduled time, which are only
+    /// shown for delayed departures
+    pub delay_width: u32,
+    pub scheduled_width: u32,
+}
+
+impl Layout {
+    fn validate(&self) -> std::result::Result<(), String> {
+        above_zero("layout.font_size", self.font_size)?;
+        above_zero("layout.small_font_size", self.small_font_size)?;
+        above_zero("layout.row_height", self.row_height as f32)
+    }
+}
+
+impl Default for Layout {
+    fn default() -> Layout {
+        Layout {
+            font_size: 32.0,
+            small_font_size: 20.0,
+            row_height: 32,
+            header_height: 32,
+            alert_height: 24,
+            clock_width: 130,
+            mode_width: 70,
+            line_width: 60,
+            site_width: 100,
+            time_width: 90,
+            delay_width: 36,
+            scheduled_width: 44,
+        }
+    }
+}
+
+/// Colours, as [red, green, blue] between 0 and 1.
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Theme {
+    /// The clock and headlines
+    pub text: Rgb,
+    /// Departures there's time to catch
+    pub departure: Rgb,
+    /// Departures to leave for right away
+    pub hurry: Rgb,
+    /// Departures that can only be caught by running
+    pub run: Rgb,
+    pub unreachable: Rgb,
+    pub alert: Rgb,
+    /// Disruption markers and the staleness badge
+    pub warning: Rgb,
+    pub delay: Rgb,
+    pub severe_delay: Rgb,
+    /// Notes about changes since the last refresh
+    pub note: Rgb,
+    pub site: Rgb,
+}
+
+impl Theme {
+    pub fn reachability(&self, reachability: Reachability) -> Rgb {
+        match reachability {
+            Reachability::Relaxed => self.departure,
+            Reachability::Hurry => self.hurry,
+            Reachability::Run => self.run,
+            Reachability::Unreachable => self.unreachable,
+        }
+    }
+}
+
+impl Default for Theme {
+    fn default() -> Theme {
+        Theme {
+            text: [1.0, 1.0, 1.0],
+            departure: [1.0, 1.0, 0.0],
+            hurry: [1.0, 0.6, 0.0],
+            run: [1.0, 0.3, 0.3],
+            unreachable: [0.4, 0.4, 0.4],
+            alert: [1.0, 0.6, 0.0],
+            warning: [1.0, 0.3, 0.3],
+            delay: [1.0, 0.6, 0.0],
+            severe_delay: [1.0, 0.2, 1.0],
+            note: [0.8, 0.8, 0.8],
+            site: [0.7, 0.7, 0.7],
+        }
+    }
+}
+
+/// Reads a value the same way as on the command line.
+fn above_zero(key: &str, value: f32) -> std::result::Result<(), String> {
+    if value > 0.0 {
+        Ok(())
+    } else {
+        Err(format!("{}: must be above zero", key))
+    }
+}
+
+fn parsed<'de, D, T>(deserializer: D) -> std::result::Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: FromStr,
+    T::Err: Display,
+{
+    String::deserialize(deserializer)?
+        .parse()
+        .map_err(de::Error::custom)
+}
+
+/// Like `parsed`, for lists of values.
+fn parsed_list<'de, D, T>(deserializer: D) -> std::result::Result<Vec<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: FromStr,
+    T::Err: Display,
+{
+    Vec::<String>::deserialize(deserializer)?
+        .iter()
+        .map(|x| x.parse().map_err(de::Error::custom))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_example_config() {
+        let config = Config::load("./triportreat.example.toml").unwrap();
+
+        assert_eq!(config.source, "sl");
+        assert_eq!(config.stations.len(), 2);
+        assert_eq!(config.stations[1].walk_time, 4);
+        assert_eq!(config.filters.modes, vec![TransportMode::Metro, TransportMode::Bus]);
+        assert_eq!(config.polling.commute.len(), 1);
+        assert_eq!(config.hardware.button_pin, 27);
+        match config.source_config().unwrap() {
+            SourceConfig::Merged { sites } => assert_eq!(sites.len(), 2),
+            other => panic!("unexpected source: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn picks_profile_by_day_and_time() {
+        let config = Config::load("./triportreat.example.toml").unwrap();
+
+        // A Wednesday evening, and the same time on Christmas day
+        let evening = config.profile_at("2024-12-18T15:30:00Z".parse().unwrap());
+        assert_eq!(evening, Some(0));
+        assert_eq!(config.with_profile(evening).stations.len(), 1);
+        assert_eq!(config.profile_at("2024-12-25T15:30:00Z".parse().unwrap()), None);
+        assert_eq!(config.profile_at("2024-12-18T08:00:00Z".parse().unwrap()), None);
+    }
+
+    #[test]
+    fn names_offending_key() {
+        let error = |text| toml::from_str::<Config>(text).unwrap_err().to_string();
+
+        assert!(error("[layout]\nfont_size = \"big\"\n").contains("layout.font_size"));
+        assert!(error("[filters]\ninclude = [\"colour=red\"]\n").contains("filters.include"));
+        assert!(error("[theme]\nbackground = [0, 0, 0]\n").contains("background"));
+        assert!(error("timezone = \"Mars/Olympus\"\n").contains("timezone"));
+    }
+
+    #[test]
+    fn reports_missing_source_settings() {
+        let config: Config = toml::from_str("source = \"sl-transport\"\n").unwrap();
+        let error = config.source_config().unwrap_err().to_string();
+        assert!(error.starts_with("stations:"));
+    }
+
+    #[test]
+    fn validates_profiles() {
+        let text = "source = \"sl-transport\"\n\
+            [[stations]]\nid = 9192\n\
+            [[profiles]]\nname = \"Weekend\"\nstations = []\n";
+        let config: Config = toml::from_str(text).unwrap();
+        assert!(config.source_config().is_ok());
+
+        let error = config.validate().unwrap_err().to_string();
+        assert!(error.starts_with("profile Weekend: stations:"), "{}", error);
+    }
+
+    #[test]
+    fn rejects_zero_sizes_and_intervals() {
+        let error = |text| {
+            let config: Config = toml::from_str(text).unwrap();
+            config.validate_settings().unwrap_err().to_string()
+        };
+
+        assert_eq!(error("[layout]\nrow_height = 0\n"), "layout.row_height: must be above zero");
+        assert_eq!(
+            error("[polling]\nidle_interval = 0\nmonthly_budget = 1000\n"),
+            "polling.idle_interval: must be above zero"
+        );
+        assert_eq!(
+            error("[[profiles]]\nname = \"Night\"\n[profiles.layout]\nfont_size = 0.0\n"),
+            "profile Night: layout.font_size: must be above zero"
+        );
+        assert!(Config::default().validate_settings().is_ok());
+    }
+}
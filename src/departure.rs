@@ -0,0 +1,168 @@
+use chrono::{DateTime, Utc};
+use serde_derive::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum TransportMode {
+    Metro,
+    Bus,
+    Train,
+    Tram,
+    Ship,
+}
+
+impl TransportMode {
+    pub const ALL: [TransportMode; 5] = [
+        TransportMode::Metro,
+        TransportMode::Bus,
+        TransportMode::Train,
+        TransportMode::Tram,
+        TransportMode::Ship,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            TransportMode::Metro => "metro",
+            TransportMode::Bus => "bus",
+            TransportMode::Train => "train",
+            TransportMode::Tram => "tram",
+            TransportMode::Ship => "ship",
+        }
+    }
+}
+
+impl std::str::FromStr for TransportMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        TransportMode::ALL
+            .iter()
+            .copied()
+            .find(|mode| mode.name().eq_ignore_ascii_case(s))
+            .ok_or_else(|| format!("unknown transport mode: {}", s))
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Deviation {
+    /// ex: Håll avstånd - Res alltid med giltig biljett
+    pub text: String,
+    /// ex: Inställd
+    pub consequence: Option<String>,
+    /// Higher is more important
+    pub importance: u32,
+}
+
+/// Something that happened to a departure between refreshes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub enum Change {
+    /// Dropped from the data before leaving, without being reported as
+    /// cancelled
+    Vanished,
+    /// Expected later than when first seen, by this many minutes
+    Delayed(i64),
+    /// Showed up among departures that were already known
+    Added,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Departure {
+    pub transport_mode: TransportMode,
+    /// ex: 19
+    pub line: String,
+    /// ex: Hagsätra
+    pub destination: String,
+    /// Provider specific direction of travel along the line, ex: 2
+    pub direction: Option<u32>,
+    /// ex: Slussen
+    pub stop_area_name: String,
+    /// Identifies the platform or stop point within the stop area, ex: 1012
+    pub stop_point_id: String,
+    /// The platform or stop letter shown to travellers, ex: 4
+    pub stop_point_designation: Option<String>,
+    pub scheduled: DateTime<Utc>,
+    pub expected: DateTime<Utc>,
+    /// Identifies the vehicle's trip, stable across refreshes, ex: 14759
+    pub journey_id: String,
+    /// Set when the provider explicitly reports the departure as cancelled
+    #[serde(default)]
+    pub cancelled: bool,
+    /// Minutes it takes to walk to the stop, ex: 4
+    #[serde(default)]
+    pub walk_time: u32,
+    /// How the departure changed since it was first seen
+    #[serde(default)]
+    pub change: Option<Change>,
+    pub deviations: Vec<Deviation>,
+}
+
+#[cfg(test)]
+impl Departure {
+    /// A metro leaving Slussen on time right now, for tests to change what
+    /// they need.
+    pub fn example() -> Departure {
+        let now = Utc::now();
+        Departure {
+            transport_mode: TransportMode::Metro,
+            line: "19".to_string(),
+            destination: "Hagsätra".to_string(),
+            direction: Some(1),
+            stop_area_name: "Slussen".to_string(),
+            stop_point_id: "1012".to_string(),
+            stop_point_designation: Some("4".to_string()),
+            scheduled: now,
+            expected: now,
+            journey_id: "14759".to_string(),
+            cancelled: false,
+            walk_time: 0,
+            change: None,
+            deviations: vec![],
+        }
+    }
+}
+
+/// A disruption affecting the whole stop rather than a single departure.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Alert {
+    /// The mode of transport affected, if the provider says
+    pub transport_mode: Option<TransportMode>,
+    pub deviation: Deviation,
+}
+
+/// Everything a source knows about upcoming departures at a stop.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct Board {
+    /// Ordered by expected departure time
+    pub departures: Vec<Departure>,
+    pub alerts: Vec<Alert>,
+    /// How many seconds old the data already was when it was fetched
+    pub data_age: u32,
+    /// Set when the departures come from the timetable alone, without any
+    /// realtime information
+    #[serde(default)]
+    pub scheduled_only: bool,
+}
+
+impl Board {
+    pub fn new(mut departures: Vec<Departure>, alerts: Vec<Alert>, data_age: u32) -> Board {
+        departures.sort_by_key(|departure| departure.expected);
+        Board {
+            departures,
+            alerts,
+            data_age,
+            scheduled_only: false,
+        }
+    }
+}
+
+/// The texts of `deviations` in order, each only once. The same message is
+/// frequently posted for several stop areas.
+pub fn distinct_texts<'a>(deviations: impl IntoIterator<Item = &'a Deviation>) -> Vec<&'a str> {
+    let mut texts: Vec<&str> = vec![];
+    for deviation in deviations {
+        if !texts.contains(&deviation.text.as_str()) {
+            texts.push(&deviation.text);
+        }
+    }
+    texts
+}
@@ -0,0 +1,106 @@
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::time::Duration;
+
+use crate::{source::FetchError, Result};
+
+/// Delay before the first retry after a failed fetch.
+const INITIAL_BACKOFF: Duration = Duration::from_secs(2);
+
+/// Upper bound for the delay between retries.
+const MAX_BACKOFF: Duration = Duration::from_secs(10 * 60);
+
+/// Delay before retrying when the backend says we've used up our quota,
+/// since retrying sooner only makes it worse.
+const QUOTA_BACKOFF: Duration = Duration::from_secs(60 * 60);
+
+/// Exponential backoff for the given number of consecutive failures, with
+/// up to 50% jitter in either direction so that several devices that lost
+/// the network at the same time don't retry in lockstep.
+fn backoff(failures: u32) -> Duration {
+    let exponent = failures.saturating_sub(1).min(16);
+    let delay = (INITIAL_BACKOFF * 2u32.pow(exponent)).min(MAX_BACKOFF);
+    delay.mul_f64(0.5 + fastrand::f64())
+}
+
+/// Runs a fetch function on a background thread and hands the results over
+/// to the UI loop. The schedule function is called after every fetch and
+/// says how long to wait until the next one, if there's to be one. Failed
+/// fetches are retried with exponential backoff, and errors are passed on so
+/// they can be reported.
+pub struct Fetcher<T> {
+    receiver: Receiver<Result<T>>,
+    wake: Sender<()>,
+}
+
+impl<T: Send + 'static> Fetcher<T> {
+    pub fn spawn<S, F>(mut schedule: S, mut fetch: F) -> Fetcher<T>
+    where
+        S: FnMut() -> Option<Duration> + Send + 'static,
+        F: FnMut() -> Result<T> + Send + 'static,
+    {
+        let (sender, receiver) = mpsc::channel();
+        let (wake, woken) = mpsc::channel();
+
+        std::thread::spawn(move || {
+            let mut failures = 0;
+            loop {
+                let result = fetch();
+                let interval = match schedule() {
+                    Some(interval) => interval,
+                    // Nothing more to fetch
+                    None => {
+                        let _ = sender.send(result);
+                        break;
+                    }
+                };
+                let delay = match &result {
+                    Ok(_) => {
+                        failures = 0;
+                        interval
+                    }
+                    Err(e) if e.downcast_ref().is_some_and(FetchError::is_quota_exceeded) => {
+                        failures += 1;
+                        let delay = QUOTA_BACKOFF.max(interval);
+                        eprintln!("API quota exceeded, retrying in {}s", delay.as_secs());
+                        delay
+                    }
+                    Err(_) => {
+                        failures += 1;
+                        let delay = backoff(failures);
+                        eprintln!(
+                            "Fetch failed {} time(s) in a row, retrying in {}s",
+                            failures,
+                            delay.as_secs(),
+                        );
+                        delay
+                    }
+                };
+
+                if sender.send(result).is_err() {
+                    // The UI loop has gone away, so there's no one left to
+                    // deliver results to.
+                    break;
+                }
+                match woken.recv_timeout(delay) {
+                    // Several wakes in a row only need a single fetch
+                    Ok(()) => woken.try_iter().for_each(drop),
+                    Err(RecvTimeoutError::Timeout) => {}
+                    Err(RecvTimeoutError::Disconnected) => break,
+                }
+            }
+        });
+
+        Fetcher { receiver, wake }
+    }
+
+    /// Returns the most recent result delivered since the last call, if any.
+    pub fn poll(&self) -> Option<Result<T>> {
+        self.receiver.try_iter().last()
+    }
+
+    /// Fetches right away instead of waiting for the next scheduled fetch.
+    pub fn wake(&self) {
+        // Fails only if the thread has stopped, which poll will notice
+        let _ = self.wake.send(());
+    }
+}
@@ -0,0 +1,170 @@
+use crate::departure::{Departure, TransportMode};
+
+/// A single test against a departure, written as `field=value`.
+#[derive(Debug, Clone)]
+pub enum Condition {
+    /// ex: line=19
+    Line(String),
+    /// ex: direction=2
+    Direction(u32),
+    /// Matches either the stop point id or its designation, ex: stop=4
+    StopPoint(String),
+    /// ex: mode=metro
+    Mode(TransportMode),
+    /// Case insensitive, with * matching any text, ex: destination=hag*
+    Destination(String),
+}
+
+impl Condition {
+    pub fn matches(&self, departure: &Departure) -> bool {
+        match self {
+            Condition::Line(line) => departure.line.eq_ignore_ascii_case(line),
+            Condition::Direction(direction) => departure.direction == Some(*direction),
+            Condition::StopPoint(stop) => {
+                departure.stop_point_id == *stop
+                    || departure
+                        .stop_point_designation
+                        .as_ref()
+                        .is_some_and(|designation| designation.eq_ignore_ascii_case(stop))
+            }
+            Condition::Mode(mode) => departure.transport_mode == *mode,
+            Condition::Destination(pattern) => {
+                glob_match(&pattern.to_lowercase(), &departure.destination.to_lowercase())
+            }
+        }
+    }
+}
+
+/// A set of conditions that must all hold, written as a comma separated
+/// list, ex: line=19,direction=1
+#[derive(Debug, Clone)]
+pub struct Rule(pub Vec<Condition>);
+
+impl Rule {
+    pub fn matches(&self, departure: &Departure) -> bool {
+        self.0.iter().all(|condition| condition.matches(departure))
+    }
+}
+
+impl std::str::FromStr for Rule {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let conditions = s
+            .split(',')
+            .map(|condition| {
+                let pos = condition
+                    .find('=')
+                    .ok_or_else(|| format!("expected field=value: {}", condition))?;
+                let (field, value) = (condition[..pos].trim(), condition[pos + 1..].trim());
+                if value.is_empty() {
+                    return Err(format!("missing value for {}", field));
+                }
+
+                match field {
+                    "line" => Ok(Condition::Line(value.to_string())),
+                    "direction" => value
+                        .parse()
+                        .map(Condition::Direction)
+                        .map_err(|_| format!("invalid direction: {}", value)),
+                    "stop" => Ok(Condition::StopPoint(value.to_string())),
+                    "mode" => value.parse().map(Condition::Mode),
+                    "destination" => Ok(Condition::Destination(value.to_string())),
+                    _ => Err(format!(
+                        "unknown field {}, expected line, direction, stop, mode or destination",
+                        field
+                    )),
+                }
+            })
+            .collect::<Result<_, _>>()?;
+
+        Ok(Rule(conditions))
+    }
+}
+
+/// Decides which departures to show. With no include rules everything is
+/// included, and exclude rules win over include rules.
+#[derive(Debug, Clone, Default)]
+pub struct Filter {
+    pub include: Vec<Rule>,
+    pub exclude: Vec<Rule>,
+}
+
+impl Filter {
+    pub fn matches(&self, departure: &Departure) -> bool {
+        (self.include.is_empty() || self.include.iter().any(|rule| rule.matches(departure)))
+            && !self.exclude.iter().any(|rule| rule.matches(departure))
+    }
+}
+
+/// Matches `text` against `pattern`, where * stands for any text.
+fn glob_match(pattern: &str, text: &str) -> bool {
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or_default();
+    let mut rest = match text.strip_prefix(first) {
+        Some(rest) => rest,
+        None => return false,
+    };
+
+    let parts: Vec<&str> = parts.collect();
+    match parts.split_last() {
+        // No wildcard, so the whole text must have matched
+        None => rest.is_empty(),
+        Some((last, middle)) => {
+            for part in middle {
+                match rest.find(part) {
+                    Some(pos) => rest = &rest[pos + part.len()..],
+                    None => return false,
+                }
+            }
+            rest.ends_with(last)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn departure(destination: &str) -> Departure {
+        Departure {
+            destination: destination.to_string(),
+            ..Departure::example()
+        }
+    }
+
+    #[test]
+    fn parses_rules() {
+        let rule: Rule = " line=19, direction = 1,stop=4,mode=metro".parse().unwrap();
+        assert_eq!(rule.0.len(), 4);
+        assert!(rule.matches(&departure("Hagsätra")));
+        let rule: Rule = "line=19,direction=2".parse().unwrap();
+        assert!(!rule.matches(&departure("Hagsätra")));
+
+        let error = |s: &str| s.parse::<Rule>().unwrap_err();
+        assert!(error("colour=red").starts_with("unknown field colour"));
+        assert!(error("line=19,colour=red").starts_with("unknown field colour"));
+        assert_eq!(error("line="), "missing value for line");
+        assert_eq!(error("line=19,"), "expected field=value: ");
+        assert_eq!(error("direction=north"), "invalid direction: north");
+        assert!("mode=rocket".parse::<Rule>().is_err());
+    }
+
+    #[test]
+    fn globs_match_anywhere() {
+        assert!(glob_match("hag*", "hagsätra"));
+        assert!(glob_match("*sätra", "hagsätra"));
+        assert!(glob_match("h*s*a", "hagsätra"));
+        assert!(glob_match("*", ""));
+        assert!(glob_match("hagsätra", "hagsätra"));
+        assert!(!glob_match("hag", "hagsätra"));
+        assert!(!glob_match("*hag", "hagsätra"));
+        assert!(!glob_match("ab*ba", "aba"));
+
+        // Destinations are compared without regard to case
+        let rule: Rule = "destination=HAG*".parse().unwrap();
+        assert!(rule.matches(&departure("Hagsätra")));
+        assert!(!rule.matches(&departure("Farsta strand")));
+        assert!(!glob_match("Hag*", "hagsätra"));
+    }
+}
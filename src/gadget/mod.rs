@@ -15,6 +15,58 @@ fn font() -> &'static Font<'static> {
     })
 }
 
+/// Width in pixels of `text` when rendered at `size`.
+pub fn text_width(text: &str, size: f32) -> u32 {
+    font()
+        .layout(text, Scale::uniform(size), point(0.0, 0.0))
+        .last()
+        .map(|glyph| glyph.position().x + glyph.unpositioned().h_metrics().advance_width)
+        .unwrap_or(0.0)
+        .ceil() as u32
+}
+
+/// Breaks `text` into lines no wider than `width`, splitting on whitespace.
+/// Single words that are too wide get a line of their own.
+pub fn wrap_text(text: &str, size: f32, width: u32) -> Vec<String> {
+    let mut lines = vec![];
+    let mut line = String::new();
+    for word in text.split_whitespace() {
+        let candidate = if line.is_empty() {
+            word.to_string()
+        } else {
+            format!("{} {}", line, word)
+        };
+
+        if !line.is_empty() && text_width(&candidate, size) > width {
+            lines.push(std::mem::replace(&mut line, word.to_string()));
+        } else {
+            line = candidate;
+        }
+    }
+
+    if !line.is_empty() {
+        lines.push(line);
+    }
+
+    lines
+}
+
+/// The display is mounted rotated by 90 degrees, so layout coordinates are
+/// transposed and mirrored before they end up in the buffer.
+fn buffer_pos<P: PixelType>(buffer: &Buffer<P>, x: u32, y: u32) -> Option<(u32, u32)> {
+    Some((y, buffer.height().checked_sub(x)?))
+}
+
+fn clear_rect<P: PixelType>(rect: &RenderRect, buffer: &mut Buffer<P>) {
+    for y in rect.y..(rect.y + rect.height) {
+        for x in rect.x..(rect.x + rect.width) {
+            if let Some((x, y)) = buffer_pos(buffer, x, y) {
+                buffer.clear_pixel(x, y);
+            }
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct RenderRect {
     pub x: u32,
@@ -85,7 +137,7 @@ pub struct ScrollGadget<P: PixelType> {
     pub height: u32,
     pub scroll: Cell<usize>,
     pub dirty: Cell<bool>,
-    pub children: Vec<Rc<dyn Gadget<P>>>,
+    pub children: RefCell<Vec<Rc<dyn Gadget<P>>>>,
 }
 
 impl<P: PixelType> ScrollGadget<P> {
@@ -95,10 +147,28 @@ impl<P: PixelType> ScrollGadget<P> {
             height,
             scroll: Cell::new(0),
             dirty: Cell::new(true),
-            children: vec![],
+            children: RefCell::new(vec![]),
         }
     }
 
+    pub fn push(&self, child: Rc<dyn Gadget<P>>) {
+        self.children.borrow_mut().push(child);
+        self.dirty.set(true);
+    }
+
+    pub fn set_children(&self, children: Vec<Rc<dyn Gadget<P>>>) {
+        self.children.replace(children);
+        self.dirty.set(true);
+    }
+
+    pub fn len(&self) -> usize {
+        self.children.borrow().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.children.borrow().is_empty()
+    }
+
     pub fn scroll(&self, scroll: usize) {
         self.scroll.set(scroll);
         self.dirty.set(true);
@@ -115,13 +185,13 @@ impl<P: PixelType> Gadget<P> for ScrollGadget<P> {
     }
 
     fn dirty(&self) -> bool {
-        self.children.iter().any(|x| x.dirty()) || self.dirty.get()
+        self.children.borrow().iter().any(|x| x.dirty()) || self.dirty.get()
     }
 
     fn render(&self, rect: RenderRect, buffer: &mut Buffer<P>) {
         let mut offset = 0;
         let dirty = self.dirty();
-        for child in self.children.iter().skip(self.scroll.get()) {
+        for child in self.children.borrow().iter().skip(self.scroll.get()) {
             if dirty || child.dirty() {
                 let rect = RenderRect {
                     x: rect.x,
@@ -133,6 +203,19 @@ impl<P: PixelType> Gadget<P> for ScrollGadget<P> {
             }
             offset += child.height();
         }
+
+        // Wipe whatever is left below the last child, since the list
+        // may have shrunk since the previous frame.
+        if self.dirty.get() && offset < rect.height {
+            let rest = RenderRect {
+                x: rect.x,
+                y: rect.y + offset,
+                width: rect.width,
+                height: rect.height - offset,
+            };
+            clear_rect(&rest, buffer);
+        }
+
         self.dirty.set(false);
     }
 }
@@ -140,7 +223,8 @@ impl<P: PixelType> Gadget<P> for ScrollGadget<P> {
 pub struct TextGadget {
     text: RefCell<String>,
     size: f32,
-    color: Rgb,
+    color: Cell<Rgb>,
+    strikethrough: Cell<bool>,
     dirty: Cell<bool>,
     width: u32,
     height: u32,
@@ -150,7 +234,8 @@ impl TextGadget {
     pub fn new(text: String, width: u32, height: u32, color: Rgb, size: f32) -> TextGadget {
         TextGadget {
             text: RefCell::new(text),
-            color,
+            color: Cell::new(color),
+            strikethrough: Cell::new(false),
             size,
             dirty: Cell::new(true),
             width,
@@ -164,6 +249,21 @@ impl TextGadget {
             self.dirty.set(true);
         }
     }
+
+    pub fn color(&self, color: Rgb) {
+        let old_color = self.color.replace(color);
+        if color != old_color {
+            self.dirty.set(true);
+        }
+    }
+
+    /// Draws a line through the text, as for a time that no longer holds.
+    pub fn strikethrough(&self, strikethrough: bool) {
+        let old_strikethrough = self.strikethrough.replace(strikethrough);
+        if strikethrough != old_strikethrough {
+            self.dirty.set(true);
+        }
+    }
 }
 
 impl<P: PixelType> Gadget<P> for TextGadget {
@@ -189,39 +289,53 @@ impl<P: PixelType> Gadget<P> for TextGadget {
             .layout(&self.text.borrow(), scale, p)
             .collect();
 
-        for y in rect.y..(rect.y + rect.height) {
-            for x in rect.x..(rect.x + rect.width) {
-                buffer.clear_pixel(y, x);
-            }
-        }
+        clear_rect(&rect, buffer);
+
+        let color = self.color.get();
 
         for glyph in glyphs {
             if let Some(bounding_box) = glyph.pixel_bounding_box() {
                 glyph.draw(|x, y, v| {
-                    let x = buffer.height() - (x + bounding_box.min.x as u32);
-                    let y = y + bounding_box.min.y as u32;
+                    let x = x as i32 + bounding_box.min.x;
+                    let y = y as i32 + bounding_box.min.y;
 
-                    if x < rect.x { return; }
-                    if y < rect.y { return; }
-                    if x > rect.x + rect.width { return; }
-                    if y > rect.y + rect.height { return; }
+                    if x < rect.x as i32 { return; }
+                    if y < rect.y as i32 { return; }
+                    if x >= (rect.x + rect.width) as i32 { return; }
+                    if y >= (rect.y + rect.height) as i32 { return; }
 
-                    let bg = match buffer.get_pixel(y, x) {
+                    let (x, y) = match buffer_pos(buffer, x as u32, y as u32) {
+                        Some(pos) => pos,
+                        None => return,
+                    };
+
+                    let bg = match buffer.get_pixel(x, y) {
                         Some(p) => p,
                         None => return,
                     };
 
                     let output = [
-                        (v * self.color[0] + (1.0 - v) * bg[0]),
-                        (v * self.color[1] + (1.0 - v) * bg[1]),
-                        (v * self.color[2] + (1.0 - v) * bg[2]),
+                        (v * color[0] + (1.0 - v) * bg[0]),
+                        (v * color[1] + (1.0 - v) * bg[1]),
+                        (v * color[2] + (1.0 - v) * bg[2]),
                     ];
 
-                    buffer.set_pixel(y, x, output);
+                    buffer.set_pixel(x, y, output);
                 });
             }
         }
 
+        if self.strikethrough.get() {
+            // Roughly through the middle of the lower case letters
+            let y = rect.y + (v_metrics.ascent - self.size * 0.3) as u32;
+            let width = text_width(&self.text.borrow(), self.size).min(rect.width);
+            for x in rect.x..(rect.x + width) {
+                if let Some((x, y)) = buffer_pos(buffer, x, y) {
+                    buffer.set_pixel(x, y, color);
+                }
+            }
+        }
+
         self.dirty.set(false);
     }
 }
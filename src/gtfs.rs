@@ -0,0 +1,478 @@
+// Static GTFS data, as found in the zip files published by Trafiklab and
+// most other agencies. Only the rows relevant to the stops we show are kept,
+// since the full feed for a region easily runs into gigabytes.
+
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+use chrono::{DateTime, Datelike, Duration, NaiveDate, Utc};
+use chrono_tz::Tz;
+use serde::de::DeserializeOwned;
+use serde_derive::Deserialize;
+
+use crate::{
+    departure::{Board, Departure, TransportMode},
+    time, Result,
+};
+
+pub mod realtime;
+
+#[derive(Debug, Deserialize)]
+pub struct Stop {
+    pub stop_id: String,
+    /// ex: Slussen
+    pub stop_name: String,
+    pub parent_station: Option<String>,
+    /// ex: 4
+    pub platform_code: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Route {
+    pub route_id: String,
+    /// ex: 19
+    pub route_short_name: Option<String>,
+    pub route_long_name: Option<String>,
+    /// Either a basic type such as 3 for bus, or an extended type such as
+    /// 401 for metro
+    pub route_type: u32,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Trip {
+    pub route_id: String,
+    pub service_id: String,
+    pub trip_id: String,
+    /// ex: Hagsätra
+    pub trip_headsign: Option<String>,
+    pub direction_id: Option<u32>,
+}
+
+#[derive(Debug, Deserialize)]
+struct StopTimeRecord {
+    trip_id: String,
+    #[serde(default)]
+    arrival_time: String,
+    /// Only required at timepoints, and often left out at the last stop
+    departure_time: String,
+    stop_id: String,
+    stop_sequence: u32,
+    stop_headsign: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct CalendarRecord {
+    service_id: String,
+    monday: u8,
+    tuesday: u8,
+    wednesday: u8,
+    thursday: u8,
+    friday: u8,
+    saturday: u8,
+    sunday: u8,
+    start_date: String,
+    end_date: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct CalendarDateRecord {
+    service_id: String,
+    date: String,
+    /// 1 if service is added on the date, 2 if it's removed
+    exception_type: u8,
+}
+
+/// The days a service runs, from calendar.txt.
+#[derive(Debug)]
+pub struct Calendar {
+    /// Indexed by days from Monday
+    pub weekdays: [bool; 7],
+    pub start_date: NaiveDate,
+    pub end_date: NaiveDate,
+}
+
+/// A trip calling at one of our stops.
+#[derive(Debug)]
+pub struct StopTime {
+    pub stop_id: String,
+    pub stop_sequence: u32,
+    /// Seconds after the start of the service day, which may exceed 24
+    /// hours for trips running past midnight
+    pub departure_time: u32,
+    pub stop_headsign: Option<String>,
+}
+
+impl StopTime {
+    /// When the trip leaves the stop, when run on `service_date` in `tz`.
+    pub fn departure(&self, service_date: NaiveDate, tz: Tz) -> DateTime<Utc> {
+        time::service_day_start(tz, service_date) + Duration::seconds(self.departure_time.into())
+    }
+}
+
+/// The parts of a static GTFS feed needed to show departures from a set of
+/// stops.
+#[derive(Debug, Default)]
+pub struct Timetable {
+    /// Our stops, along with their parent stations
+    pub stops: HashMap<String, Stop>,
+    pub routes: HashMap<String, Route>,
+    pub trips: HashMap<String, Trip>,
+    /// Calls at our stops, by trip id
+    pub stop_times: HashMap<String, Vec<StopTime>>,
+    /// By service id
+    pub calendars: HashMap<String, Calendar>,
+    /// Dates a service is added (true) or removed (false) on, overriding
+    /// its calendar
+    pub calendar_dates: HashMap<(String, NaiveDate), bool>,
+}
+
+impl Timetable {
+    /// Loads a GTFS zip file, keeping what's relevant to `stop_ids`. Giving
+    /// the id of a station includes all of its platforms.
+    pub fn load<P: AsRef<Path>>(path: P, stop_ids: &[String]) -> Result<Timetable> {
+        let mut archive = zip::ZipArchive::new(File::open(path)?)?;
+        let mut timetable = Timetable::default();
+
+        let all_stops: Vec<Stop> = read_csv(archive.by_name("stops.txt")?)?;
+        let ours: HashSet<String> = all_stops
+            .iter()
+            .filter(|stop| {
+                stop_ids.contains(&stop.stop_id)
+                    || stop
+                        .parent_station
+                        .as_ref()
+                        .is_some_and(|parent| stop_ids.contains(parent))
+            })
+            .map(|stop| stop.stop_id.clone())
+            .collect();
+        let parents: HashSet<String> = all_stops
+            .iter()
+            .filter(|stop| ours.contains(&stop.stop_id))
+            .filter_map(|stop| stop.parent_station.clone())
+            .collect();
+        for stop in all_stops {
+            if ours.contains(&stop.stop_id) || parents.contains(&stop.stop_id) {
+                timetable.stops.insert(stop.stop_id.clone(), stop);
+            }
+        }
+
+        // stop_times.txt is by far the largest file, so stream through it
+        // rather than collecting every row
+        for record in csv::Reader::from_reader(archive.by_name("stop_times.txt")?).deserialize() {
+            let record: StopTimeRecord = record?;
+            if !ours.contains(&record.stop_id) {
+                continue;
+            }
+
+            // Stops between timepoints have no time of their own, and there's
+            // no telling when the trip calls there
+            let time = match (record.departure_time.trim(), record.arrival_time.trim()) {
+                ("", "") => continue,
+                ("", arrival_time) => arrival_time,
+                (departure_time, _) => departure_time,
+            };
+            let departure_time = parse_time(time)?;
+
+            timetable
+                .stop_times
+                .entry(record.trip_id)
+                .or_insert_with(Vec::new)
+                .push(StopTime {
+                    stop_id: record.stop_id,
+                    stop_sequence: record.stop_sequence,
+                    departure_time,
+                    stop_headsign: record.stop_headsign,
+                });
+        }
+
+        let trips: Vec<Trip> = read_csv(archive.by_name("trips.txt")?)?;
+        for trip in trips {
+            if timetable.stop_times.contains_key(&trip.trip_id) {
+                timetable.trips.insert(trip.trip_id.clone(), trip);
+            }
+        }
+
+        let route_ids: HashSet<&str> = timetable
+            .trips
+            .values()
+            .map(|trip| trip.route_id.as_str())
+            .collect();
+        let routes: Vec<Route> = read_csv(archive.by_name("routes.txt")?)?;
+        for route in routes {
+            if route_ids.contains(route.route_id.as_str()) {
+                timetable.routes.insert(route.route_id.clone(), route);
+            }
+        }
+
+        let service_ids: HashSet<&str> = timetable
+            .trips
+            .values()
+            .map(|trip| trip.service_id.as_str())
+            .collect();
+
+        // A feed may describe its services with either file, or both
+        if let Ok(file) = archive.by_name("calendar.txt") {
+            let records: Vec<CalendarRecord> = read_csv(file)?;
+            for record in records {
+                if !service_ids.contains(record.service_id.as_str()) {
+                    continue;
+                }
+
+                let calendar = Calendar {
+                    weekdays: [
+                        record.monday == 1,
+                        record.tuesday == 1,
+                        record.wednesday == 1,
+                        record.thursday == 1,
+                        record.friday == 1,
+                        record.saturday == 1,
+                        record.sunday == 1,
+                    ],
+                    start_date: parse_date(&record.start_date)?,
+                    end_date: parse_date(&record.end_date)?,
+                };
+                timetable.calendars.insert(record.service_id, calendar);
+            }
+        }
+
+        if let Ok(file) = archive.by_name("calendar_dates.txt") {
+            let records: Vec<CalendarDateRecord> = read_csv(file)?;
+            for record in records {
+                if !service_ids.contains(record.service_id.as_str()) {
+                    continue;
+                }
+
+                let date = parse_date(&record.date)?;
+                timetable
+                    .calendar_dates
+                    .insert((record.service_id, date), record.exception_type == 1);
+            }
+        }
+
+        Ok(timetable)
+    }
+
+    /// Whether trips belonging to `service_id` run on `date`.
+    pub fn runs_on(&self, service_id: &str, date: NaiveDate) -> bool {
+        if let Some(&added) = self.calendar_dates.get(&(service_id.to_string(), date)) {
+            return added;
+        }
+
+        match self.calendars.get(service_id) {
+            Some(calendar) => {
+                calendar.start_date <= date
+                    && date <= calendar.end_date
+                    && calendar.weekdays[date.weekday().num_days_from_monday() as usize]
+            }
+            None => false,
+        }
+    }
+
+    /// The departure of `trip_id` from the stop in `call`, according to the
+    /// timetable, when run on `service_date` in `tz`.
+    pub fn departure(
+        &self,
+        trip_id: &str,
+        call: &StopTime,
+        service_date: NaiveDate,
+        tz: Tz,
+    ) -> Option<Departure> {
+        let trip = self.trips.get(trip_id)?;
+        let route = self.routes.get(&trip.route_id)?;
+        let scheduled = call.departure(service_date, tz);
+
+        Some(Departure {
+            transport_mode: transport_mode(route.route_type)?,
+            line: route
+                .route_short_name
+                .clone()
+                .or_else(|| route.route_long_name.clone())
+                .unwrap_or_else(|| route.route_id.clone()),
+            destination: call
+                .stop_headsign
+                .clone()
+                .or_else(|| trip.trip_headsign.clone())
+                .unwrap_or_default(),
+            direction: trip.direction_id,
+            stop_area_name: self
+                .stop_area_name(&call.stop_id)
+                .unwrap_or_default()
+                .to_string(),
+            stop_point_id: call.stop_id.clone(),
+            stop_point_designation: self
+                .stops
+                .get(&call.stop_id)
+                .and_then(|stop| stop.platform_code.clone()),
+            scheduled,
+            expected: scheduled,
+            journey_id: trip.trip_id.clone(),
+            cancelled: false,
+            walk_time: 0,
+            change: None,
+            deviations: vec![],
+        })
+    }
+
+    /// Scheduled departures in the `window` following `now`, for a timetable
+    /// in `tz`.
+    pub fn scheduled_board(&self, now: DateTime<Utc>, window: Duration, tz: Tz) -> Board {
+        let mut departures = vec![];
+
+        // Trips running past midnight belong to the previous day's service
+        let today = time::local_date(tz, now);
+        for service_date in &[today.pred(), today] {
+            for (trip_id, calls) in &self.stop_times {
+                let runs = self
+                    .trips
+                    .get(trip_id)
+                    .is_some_and(|trip| self.runs_on(&trip.service_id, *service_date));
+                if !runs {
+                    continue;
+                }
+
+                departures.extend(
+                    calls
+                        .iter()
+                        .filter(|call| {
+                            let departure = call.departure(*service_date, tz);
+                            now <= departure && departure < now + window
+                        })
+                        .filter_map(|call| self.departure(trip_id, call, *service_date, tz)),
+                );
+            }
+        }
+
+        let mut board = Board::new(departures, vec![], 0);
+        board.scheduled_only = true;
+        board
+    }
+
+    /// The name travellers know the stop by, which is the station's name
+    /// for platforms that belong to one.
+    pub fn stop_area_name(&self, stop_id: &str) -> Option<&str> {
+        let stop = self.stops.get(stop_id)?;
+        let parent = stop
+            .parent_station
+            .as_ref()
+            .and_then(|parent| self.stops.get(parent));
+
+        Some(&parent.unwrap_or(stop).stop_name)
+    }
+}
+
+fn read_csv<T: DeserializeOwned, R: Read>(reader: R) -> Result<Vec<T>> {
+    Ok(csv::Reader::from_reader(reader)
+        .deserialize()
+        .collect::<std::result::Result<_, _>>()?)
+}
+
+/// Parses a GTFS date such as 20240312.
+fn parse_date(date: &str) -> Result<NaiveDate> {
+    Ok(NaiveDate::parse_from_str(date.trim(), "%Y%m%d")?)
+}
+
+/// Parses a GTFS time such as 25:10:00 into seconds.
+fn parse_time(time: &str) -> Result<u32> {
+    let parts = time
+        .trim()
+        .split(':')
+        .map(|part| part.parse())
+        .collect::<std::result::Result<Vec<u32>, _>>()?;
+
+    match parts.as_slice() {
+        [hours, minutes, seconds] => Ok(hours * 3600 + minutes * 60 + seconds),
+        _ => Err(format!("invalid time: {}", time).into()),
+    }
+}
+
+/// Maps basic and extended GTFS route types onto the modes we show.
+pub fn transport_mode(route_type: u32) -> Option<TransportMode> {
+    match route_type {
+        0 | 900..=999 => Some(TransportMode::Tram),
+        1 | 400..=499 => Some(TransportMode::Metro),
+        2 | 100..=199 => Some(TransportMode::Train),
+        3 | 11 | 200..=299 | 700..=899 => Some(TransportMode::Bus),
+        4 | 1000..=1099 | 1200..=1299 => Some(TransportMode::Ship),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn timetable() -> Timetable {
+        Timetable::load("./test/data/gtfs.zip", &["9001".to_string()]).unwrap()
+    }
+
+    #[test]
+    fn parses_times_past_midnight() {
+        assert_eq!(parse_time("08:05:30").unwrap(), 8 * 3600 + 5 * 60 + 30);
+        assert_eq!(parse_time(" 25:10:00").unwrap(), 25 * 3600 + 10 * 60);
+        assert!(parse_time("25:10").is_err());
+        assert!(parse_time("").is_err());
+    }
+
+    #[test]
+    fn keeps_what_calls_at_our_stops() {
+        let timetable = timetable();
+        assert_eq!(timetable.stops.len(), 3);
+        assert_eq!(timetable.stop_area_name("9011"), Some("Slussen"));
+        assert_eq!(timetable.routes.keys().collect::<Vec<_>>(), vec!["19"]);
+        assert!(!timetable.trips.contains_key("bus"));
+
+        // The untimed call is left out, and the last stop only has an arrival
+        assert_eq!(timetable.stop_times["late"].len(), 1);
+        assert_eq!(
+            timetable.stop_times["terminus"][0].departure_time,
+            23 * 3600 + 40 * 60
+        );
+    }
+
+    #[test]
+    fn calendar_dates_override_weekdays() {
+        let timetable = timetable();
+        let date = |day| NaiveDate::from_ymd(2024, 3, day);
+
+        assert!(timetable.runs_on("weekday", date(11)));
+        // Removed on a Tuesday, and added on a Saturday
+        assert!(!timetable.runs_on("weekday", date(12)));
+        assert!(timetable.runs_on("weekday", date(16)));
+        assert!(!timetable.runs_on("weekday", date(17)));
+        assert!(!timetable.runs_on("other", date(11)));
+    }
+
+    #[test]
+    fn trips_past_midnight_run_on_the_previous_day() {
+        let timetable = timetable();
+        let tz = chrono_tz::Europe::Stockholm;
+        let local = |day, hour, minute| {
+            tz.ymd(2024, 3, day)
+                .and_hms(hour, minute, 0)
+                .with_timezone(&Utc)
+        };
+
+        // Thursday evening, with the late trip leaving after midnight
+        let board = timetable.scheduled_board(local(14, 23, 45), Duration::hours(2), tz);
+        assert!(board.scheduled_only);
+        let mut departures: Vec<_> = board
+            .departures
+            .iter()
+            .map(|departure| (departure.journey_id.as_str(), departure.scheduled))
+            .collect();
+        departures.sort_by_key(|&(_, scheduled)| scheduled);
+        assert_eq!(
+            departures,
+            vec![("evening", local(14, 23, 50)), ("late", local(15, 1, 10))]
+        );
+
+        // Tuesday's service is cancelled, so nothing runs past midnight
+        let board = timetable.scheduled_board(local(13, 0, 30), Duration::hours(1), tz);
+        assert!(board.departures.is_empty());
+        let board = timetable.scheduled_board(local(12, 0, 30), Duration::hours(1), tz);
+        assert_eq!(board.departures.len(), 1);
+    }
+}
@@ -1,3 +1,5 @@
+use serde_derive::Deserialize;
+
 use crate::{buffer::{Buffer,PixelType}, Result};
 
 #[cfg(feature = "hw-pi")]
@@ -5,18 +7,44 @@ pub mod pi;
 #[cfg(feature = "hw-sdl")]
 pub mod sdl;
 
+/// Where the display and controls are connected. Only used on the Pi.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+#[cfg_attr(not(feature = "hw-pi"), allow(dead_code))]
+pub struct HardwareConfig {
+    /// ex: /dev/fb1
+    pub framebuffer: String,
+    /// BCM numbers of the GPIO pins the rotary encoder is connected to
+    pub encoder_pins: [u8; 2],
+    pub button_pin: u8,
+    /// Blinking while the board runs
+    pub led_pins: [u8; 2],
+}
+
+impl Default for HardwareConfig {
+    fn default() -> HardwareConfig {
+        HardwareConfig {
+            framebuffer: "/dev/fb1".to_string(),
+            encoder_pins: [17, 18],
+            button_pin: 27,
+            led_pins: [22, 23],
+        }
+    }
+}
+
 #[cfg(feature = "hw-pi")]
-pub fn create_hardware() -> Result<pi::PiHardware> {
-    pi::PiHardware::open("/dev/fb1")
+pub fn create_hardware(config: &HardwareConfig) -> Result<pi::PiHardware> {
+    pi::PiHardware::open(config)
 }
 
 #[cfg(feature = "hw-sdl")]
-pub fn create_hardware() -> Result<sdl::SdlHardware> {
+pub fn create_hardware(_config: &HardwareConfig) -> Result<sdl::SdlHardware> {
     sdl::SdlHardware::open()
 }
 
 pub enum HwEvent {
     Scroll(isize),
+    Button,
 }
 
 pub trait Hardware<P: PixelType> {
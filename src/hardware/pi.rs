@@ -6,6 +6,7 @@ use rppal::gpio::{Gpio, Level, Trigger};
 
 use crate::{
     buffer::Buffer,
+    hardware::HardwareConfig,
     Error, Hardware, HwEvent, Result,
 };
 
@@ -16,7 +17,7 @@ struct GPIOState {
     counter: i32,
 }
 
-fn gpio_handler(events: Arc<Mutex<Vec<HwEvent>>>) -> Result<()> {
+fn gpio_handler(events: Arc<Mutex<Vec<HwEvent>>>, config: HardwareConfig) -> Result<()> {
     let state = Arc::new(Mutex::new(GPIOState {
         pin1: false,
         pin2: false,
@@ -25,11 +26,12 @@ fn gpio_handler(events: Arc<Mutex<Vec<HwEvent>>>) -> Result<()> {
 
     let gpio = Gpio::new()?;
 
-    let pin1 = Arc::new(Mutex::new(gpio.get(17)?.into_input_pulldown()));
-    let pin2 = Arc::new(Mutex::new(gpio.get(18)?.into_input_pulldown()));
-    let mut button = gpio.get(27)?.into_input_pullup();
-    let mut led1 = gpio.get(22)?.into_output();
-    let mut led2 = gpio.get(23)?.into_output();
+    let [pin1, pin2] = config.encoder_pins;
+    let pin1 = Arc::new(Mutex::new(gpio.get(pin1)?.into_input_pulldown()));
+    let pin2 = Arc::new(Mutex::new(gpio.get(pin2)?.into_input_pulldown()));
+    let mut button = gpio.get(config.button_pin)?.into_input_pullup();
+    let mut led1 = gpio.get(config.led_pins[0])?.into_output();
+    let mut led2 = gpio.get(config.led_pins[1])?.into_output();
 
     let handler = {
         let pin1 = pin1.clone();
@@ -71,8 +73,12 @@ fn gpio_handler(events: Arc<Mutex<Vec<HwEvent>>>) -> Result<()> {
             move |_| handler(2)
         })?;
 
-    button.set_async_interrupt(Trigger::FallingEdge, |_| {
-        eprintln!("Button pressed");
+    button.set_async_interrupt(Trigger::FallingEdge, {
+        let events = events.clone();
+        move |_| {
+            eprintln!("Button pressed");
+            events.lock().unwrap().push(HwEvent::Button);
+        }
     })?;
 
     let mut lights_on = false;
@@ -116,7 +122,8 @@ pub struct PiHardware {
 }
 
 impl PiHardware {
-    pub fn open(device: &str) -> Result<Self> {
+    pub fn open(config: &HardwareConfig) -> Result<Self> {
+        let device = &config.framebuffer;
         let (_finf, vinf) = {
             let file = File::open(device)?;
             let finf = dbg!(Framebuffer::get_fix_screeninfo(&file)?);
@@ -137,7 +144,8 @@ impl PiHardware {
 
         std::thread::spawn({
             let events = events.clone();
-            move || gpio_handler(events)
+            let config = config.clone();
+            move || gpio_handler(events, config)
         });
 
         Ok(PiHardware {
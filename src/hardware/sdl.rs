@@ -70,6 +70,12 @@ impl Hardware<[u8; 3]> for SdlHardware {
                 } => {
                     events.push(HwEvent::Scroll(-1));
                 }
+                Event::KeyDown {
+                    keycode: Some(Keycode::Space),
+                    ..
+                } => {
+                    events.push(HwEvent::Button);
+                }
                 _ => {}
             }
         }
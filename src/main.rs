@@ -1,204 +1,984 @@
-use std::rc::Rc;
+use std::path::Path;
+use std::sync::Arc;
 
 use argh::FromArgs;
+use chrono::{DateTime, Utc};
+use chrono_tz::Tz;
 
 use crate::{
-    gadget::{Gadget, HorizontalGadget, ScrollGadget, TextGadget, RenderRect},
+    buffer::PixelType,
+    clock::Clock,
+    config::{Config, GtfsConfig, PollingConfig, Station},
+    departure::{Board, TransportMode},
+    fetcher::Fetcher,
+    filter::{Filter, Rule},
+    reachability::StopPointWalkTime,
+    reload::ReloadTrigger,
+    schedule::{Budget, PollSchedule, ReplaySchedule, TimeRange},
+    screen::Screen,
+    search::Coordinates,
+    source::{record::Recorder, replay::Recording, SourceConfig},
+    state::Snapshot,
+    tracker::JourneyTracker,
     hardware::{create_hardware, Hardware, HwEvent},
 };
 
 pub mod buffer;
+pub mod calendar;
+pub mod clock;
+pub mod config;
+pub mod departure;
+pub mod fetcher;
+pub mod filter;
 pub mod hardware;
 pub mod gadget;
+pub mod gtfs;
+pub mod reachability;
+pub mod reload;
+pub mod schedule;
+pub mod screen;
+pub mod search;
+pub mod source;
+pub mod state;
+pub mod time;
+pub mod tracker;
+mod trafiklab;
 
 type Error = Box<dyn std::error::Error + Sync + Send + 'static>;
 type Result<T> = std::result::Result<T, Error>;
 
-/// Trip or Treat
+/// Read when no --config is given, if it exists.
+const DEFAULT_CONFIG: &str = "triportreat.toml";
+
+/// Trip or Treat. Shows the departure board unless another command is
+/// given. Flags override the settings in the config file, and go before the
+/// command.
 #[derive(FromArgs)]
 struct Opt {
-    /// activate debug mode
-    #[argh(option, default = "false")]
+    /// activate debug mode, same as --source fixture
+    #[argh(switch)]
     debug: bool,
 
-    /// station id
+    /// config file to read, defaults to triportreat.toml if there is one
+    #[argh(option)]
+    config: Option<String>,
+
+    /// where to get departures from: sl (the default), sl-transport,
+    /// gtfs-rt, siri or fixture
+    #[argh(option)]
+    source: Option<String>,
+
+    /// station id, optionally followed by the minutes it takes to walk
+    /// there, ex: 9192:4. Can be repeated to show several stations on one
+    /// board. Required by the sl and sl-transport sources
+    #[argh(option, long = "station-id")]
+    stations: Vec<Station>,
+
+    /// trafiklab api key, required by the sl source
+    #[argh(option)]
+    api_key: Option<String>,
+
+    /// gtfs-realtime TripUpdates feed, URL or file, for the gtfs-rt source
+    #[argh(option)]
+    gtfs_rt_feed: Option<String>,
+
+    /// static gtfs zip file, for the gtfs-rt source and to show the
+    /// timetable from when there's no realtime data
+    #[argh(option)]
+    gtfs_static: Option<String>,
+
+    /// gtfs stop_id to show departures from, can be repeated
+    #[argh(option, long = "gtfs-stop")]
+    gtfs_stops: Vec<String>,
+
+    /// siri stopmonitoring service, URL or file, for the siri source
+    #[argh(option)]
+    siri_feed: Option<String>,
+
+    /// recorded response to show with the fixture source, defaults to
+    /// ./test/data/sl.json
+    #[argh(option)]
+    fixture: Option<String>,
+
+    /// seconds between departure refreshes during commute hours, or all day
+    /// if no --commute is given. Defaults to 60
+    #[argh(option)]
+    refresh_interval: Option<u64>,
+
+    /// commute hours, when departures are refreshed every
+    /// --refresh-interval, ex: 07:00-09:30. Can be repeated
+    #[argh(option)]
+    commute: Vec<TimeRange>,
+
+    /// seconds between departure refreshes outside commute hours, defaults
+    /// to 300
+    #[argh(option)]
+    idle_interval: Option<u64>,
+
+    /// hours when the display sleeps and nothing is fetched, ex:
+    /// 00:30-05:30. Can be repeated. Turning the knob or pressing the
+    /// button wakes it for a few minutes
+    #[argh(option)]
+    night: Vec<TimeRange>,
+
+    /// api calls allowed per month. Refreshes are spread out so that they
+    /// last the whole month
+    #[argh(option)]
+    monthly_budget: Option<u32>,
+
+    /// file to count this month's api calls in, defaults to api_usage.json
+    #[argh(option)]
+    budget_file: Option<String>,
+
+    /// timezone of the departure times, and of the clock. Defaults to
+    /// Europe/Stockholm
+    #[argh(option)]
+    timezone: Option<Tz>,
+
+    /// file to keep the last successful response in, so that there's
+    /// something to show when starting without network. Defaults to
+    /// last_response.json
     #[argh(option)]
-    station_id: u32,
+    state_file: Option<String>,
 
-    /// trafiklab api key
+    /// seconds after which realtime data is replaced by the timetable from
+    /// --gtfs-static, defaults to 300
+    #[argh(option)]
+    fallback_after: Option<i64>,
+
+    /// transport mode to show (metro, bus, train, tram or ship), can be
+    /// repeated, defaults to all of them
+    #[argh(option, long = "mode")]
+    modes: Vec<TransportMode>,
+
+    /// only show departures matching this rule, can be repeated. A rule is
+    /// a comma separated list of line, direction, stop, mode or destination
+    /// conditions that must all hold, ex: line=19,direction=1 or
+    /// destination=hag*
+    #[argh(option)]
+    include: Vec<Rule>,
+
+    /// hide departures matching this rule, can be repeated. Takes precedence
+    /// over --include
+    #[argh(option)]
+    exclude: Vec<Rule>,
+
+    /// minutes it takes to walk to the departures, used to colour them by
+    /// whether they can be caught
+    #[argh(option)]
+    walk_time: Option<u32>,
+
+    /// minutes a departure must differ from the timetable by before the
+    /// delay is shown, defaults to 2
+    #[argh(option)]
+    delay_threshold: Option<i64>,
+
+    /// minutes of delay from which a departure counts as severely delayed,
+    /// defaults to 10
+    #[argh(option)]
+    severe_delay: Option<i64>,
+
+    /// walk time for a single stop point, by id or designation, overriding
+    /// --walk-time and the station's walk time, ex: 4=3. Can be repeated
+    #[argh(option, long = "stop-walk-time")]
+    stop_walk_times: Vec<StopPointWalkTime>,
+
+    #[argh(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(FromArgs)]
+#[argh(subcommand)]
+enum Command {
+    Run(RunCommand),
+    Fetch(FetchCommand),
+    Render(RenderCommand),
+    Replay(ReplayCommand),
+    Record(RecordCommand),
+    CheckConfig(CheckConfigCommand),
+    Search(SearchCommand),
+}
+
+/// Show the departure board on the display, the default
+#[derive(FromArgs)]
+#[argh(subcommand, name = "run")]
+struct RunCommand {}
+
+/// Fetch the departures once and print them
+#[derive(FromArgs)]
+#[argh(subcommand, name = "fetch")]
+struct FetchCommand {
+    /// print the board as json rather than as a table
+    #[argh(switch)]
+    json: bool,
+}
+
+/// Fetch the departures once and draw the board to a png file, without
+/// any display
+#[derive(FromArgs)]
+#[argh(subcommand, name = "render")]
+struct RenderCommand {
+    /// file to write the image to
+    #[argh(option, default = "String::from(\"board.png\")")]
+    output: String,
+
+    /// width of the display, in pixels
+    #[argh(option, default = "320")]
+    width: u32,
+
+    /// height of the display, in pixels
+    #[argh(option, default = "480")]
+    height: u32,
+}
+
+/// Show a recording on the display, with the clock turned back to when it
+/// was made
+#[derive(FromArgs)]
+#[argh(subcommand, name = "replay")]
+struct ReplayCommand {
+    /// directory of responses named by when they were fetched, ex:
+    /// 20201226T231047Z-sl-1011.json
+    #[argh(positional)]
+    recording: String,
+
+    /// how many times faster than real time to play the recording, defaults
+    /// to 1
+    #[argh(option, default = "1.0")]
+    speed: f64,
+}
+
+/// Poll the configured source and save every response, as a recording to
+/// replay later
+#[derive(FromArgs)]
+#[argh(subcommand, name = "record")]
+struct RecordCommand {
+    /// directory to save the responses in, created if needed
+    #[argh(positional)]
+    recording: String,
+
+    /// blank out the api key in case it turns up in a response, so that
+    /// the recording can be shared
+    #[argh(switch)]
+    scrub_api_key: bool,
+}
+
+/// Check the config file and flags, without showing anything
+#[derive(FromArgs)]
+#[argh(subcommand, name = "check-config")]
+struct CheckConfigCommand {}
+
+/// Find the site ids of stations, by name or by position
+#[derive(FromArgs)]
+#[argh(subcommand, name = "search")]
+struct SearchCommand {
+    /// station name, or the start of it, ex: slussen
+    #[argh(positional)]
+    query: Option<String>,
+
+    /// latitude,longitude to list the stops around instead, ex:
+    /// 59.3203,18.0719
+    #[argh(option)]
+    near: Option<Coordinates>,
+
+    /// meters around --near to look for stops in
+    #[argh(option, default = "1000")]
+    radius: u32,
+
+    /// trafiklab api key for SL Platsuppslag, or for SL Närliggande
+    /// hållplatser 2 when using --near
     #[argh(option)]
     api_key: String,
 }
 
-mod trafiklab {
-    use chrono::prelude::*;
-    use serde_derive::Deserialize;
-
-    #[derive(Debug, Deserialize)]
-    #[serde(rename_all = "PascalCase")]
-    pub struct RealtimeDepartureInfo {
-        /// ex: tunnelbanans gröna linje
-        pub group_of_line: String,
-        /// ex: 1 min
-        pub display_time: String,
-        /// ex: METRO
-        pub transport_mode: String,
-        /// ex: Hagsätra
-        pub destination: String,
-        /// ex: 2
-        pub journey_direction: u32,
-        /// ex: Slussen
-        pub stop_area_name: String,
-        /// ex: 1011
-        pub stop_area_number: u32,
-        /// ex: 1012
-        pub stop_point_number: u32,
-        /// ex: 4
-        pub stop_point_designation: String,
-        /// ex: 2020-12-27T00:12:00
-        pub time_tabled_date_time: NaiveDateTime,
-        /// ex: 2020-12-27T00:12:00
-        pub expected_date_time: NaiveDateTime,
-        /// ex: 14759
-        pub journey_number: u32,
-    }
-
-    #[derive(Debug, Deserialize)]
-    #[serde(rename_all = "PascalCase")]
-    pub struct RealtimeDeparturesResponseData {
-        pub metros: Vec<RealtimeDepartureInfo>,
-    }
-
-    #[derive(Debug, Deserialize)]
-    #[serde(rename_all = "PascalCase")]
-    pub struct RealtimeDeparturesV4Response {
-        pub response_data: RealtimeDeparturesResponseData,
-    }
-}
-
-fn read_test_data() -> Result<trafiklab::RealtimeDeparturesResponseData> {
-    let trafiklab::RealtimeDeparturesV4Response { response_data } =
-        serde_json::from_str(&std::fs::read_to_string("./test/data/sl.json")?)?;
-
-    Ok(response_data)
-}
-
-fn fetch_from_server(
-    api_key: &str,
-    station_id: u32,
-) -> Result<trafiklab::RealtimeDeparturesResponseData> {
-    let url = format!(
-        "https://api.sl.se/api2/realtimedeparturesV4.json?key={}&siteid={}&timewindow=60",
-        api_key, station_id,
-    );
-    let response = ureq::get(&url).call();
-    if !response.ok() {
-        panic!("error {}: {}", response.status(), response.into_string()?);
+/// Prints the stops matching `command`, one per line, with their site ids.
+fn search_stops(command: &SearchCommand) -> Result<()> {
+    let stops = match (&command.query, command.near) {
+        (Some(query), None) => search::by_name(&command.api_key, query)?,
+        (None, Some(position)) => search::nearby(&command.api_key, position, command.radius)?,
+        _ => return Err("search needs either a station name or --near".into()),
+    };
+
+    if stops.is_empty() {
+        println!("No stops found");
+    }
+    for stop in stops {
+        println!("{}", stop);
+    }
+
+    Ok(())
+}
+
+fn config_path(opt: &Opt) -> &Path {
+    Path::new(opt.config.as_deref().unwrap_or(DEFAULT_CONFIG))
+}
+
+/// Reads the config file, if any, and lets the flags in `opt` override it.
+fn load_config(opt: &Opt) -> Result<Config> {
+    let path = config_path(opt);
+    let mut config = if opt.config.is_some() || path.exists() {
+        Config::load(path)?
+    } else {
+        Config::default()
+    };
+
+    if opt.debug {
+        config.source = "fixture".to_string();
+    }
+    if let Some(source) = &opt.source {
+        config.source = source.clone();
+    }
+    if !opt.stations.is_empty() {
+        config.stations = opt.stations.clone();
+    }
+    if opt.api_key.is_some() {
+        config.api_key = opt.api_key.clone();
+    }
+    if opt.gtfs_rt_feed.is_some() {
+        config.gtfs.realtime_feed = opt.gtfs_rt_feed.clone();
+    }
+    if opt.gtfs_static.is_some() {
+        config.gtfs.static_path = opt.gtfs_static.clone();
+    }
+    if !opt.gtfs_stops.is_empty() {
+        config.gtfs.stops = opt.gtfs_stops.clone();
+    }
+    if opt.siri_feed.is_some() {
+        config.siri_feed = opt.siri_feed.clone();
+    }
+    if let Some(fixture) = &opt.fixture {
+        config.fixture = fixture.clone();
+    }
+    if let Some(timezone) = opt.timezone {
+        config.timezone = timezone;
+    }
+    if let Some(state_file) = &opt.state_file {
+        config.state_file = state_file.clone();
+    }
+    if let Some(fallback_after) = opt.fallback_after {
+        config.fallback_after = fallback_after;
+    }
+
+    let polling = &mut config.polling;
+    if let Some(refresh_interval) = opt.refresh_interval {
+        polling.refresh_interval = refresh_interval;
+    }
+    if let Some(idle_interval) = opt.idle_interval {
+        polling.idle_interval = idle_interval;
+    }
+    if !opt.commute.is_empty() {
+        polling.commute = opt.commute.clone();
+    }
+    if !opt.night.is_empty() {
+        polling.night = opt.night.clone();
+    }
+    if opt.monthly_budget.is_some() {
+        polling.monthly_budget = opt.monthly_budget;
+    }
+    if let Some(budget_file) = &opt.budget_file {
+        polling.budget_file = budget_file.clone();
     }
 
-    let trafiklab::RealtimeDeparturesV4Response { response_data } =
-        response.into_json_deserialize()?;
+    let filters = &mut config.filters;
+    if !opt.modes.is_empty() {
+        filters.modes = opt.modes.clone();
+    }
+    if !opt.include.is_empty() {
+        filters.include = opt.include.clone();
+    }
+    if !opt.exclude.is_empty() {
+        filters.exclude = opt.exclude.clone();
+    }
 
-    Ok(response_data)
+    if let Some(walk_time) = opt.walk_time {
+        config.walk_time = walk_time;
+    }
+    for x in &opt.stop_walk_times {
+        config.stop_walk_times.insert(x.stop_point.clone(), x.walk_time);
+    }
+    if let Some(threshold) = opt.delay_threshold {
+        config.delays.threshold = threshold;
+    }
+    if let Some(severe) = opt.severe_delay {
+        config.delays.severe = severe;
+    }
+
+    Ok(config)
 }
 
 fn main() -> Result<()> {
     let opt: Opt = argh::from_env();
 
-    let data = if opt.debug {
-        dbg!(read_test_data()?)
+    match &opt.command {
+        None | Some(Command::Run(_)) => show_board(&opt, None),
+        Some(Command::Replay(command)) => show_board(&opt, Some(command)),
+        Some(Command::Record(command)) => record_responses(&opt, command),
+        Some(Command::Fetch(command)) => print_departures(&opt, command),
+        Some(Command::Render(command)) => render_board(&opt, command),
+        Some(Command::CheckConfig(_)) => check_config(&opt),
+        Some(Command::Search(command)) => search_stops(command),
+    }
+}
+
+/// Validates the config, and tells what it sets up.
+fn check_config(opt: &Opt) -> Result<()> {
+    let config = load_config(opt)?;
+    config.validate()?;
+
+    println!("Config OK");
+    println!("Source: {}", config.source);
+    if !config.profiles.is_empty() {
+        let names: Vec<_> = config.profiles.iter().map(|x| x.name.as_str()).collect();
+        println!("Profiles: {}", names.join(", "));
+        let current = config.profile_at(Utc::now());
+        println!(
+            "Current profile: {}",
+            current.map_or("none", |index| config.profiles[index].name.as_str())
+        );
+    }
+
+    Ok(())
+}
+
+/// The name to show for the profile at `index`. There's room for one in the
+/// header as soon as the config has any profiles.
+fn profile_name(config: &Config, index: Option<usize>) -> Option<&str> {
+    if config.profiles.is_empty() {
+        return None;
+    }
+    Some(index.map_or("", |index| config.profiles[index].name.as_str()))
+}
+
+/// Fetches the board once, for the profile that applies right now. Returns
+/// the config with the profile in place, along with the profile's index.
+fn fetch_board(opt: &Opt) -> Result<(Config, Option<usize>, Board)> {
+    let base = load_config(opt)?;
+    base.validate_settings()?;
+    let profile = base.profile_at(Utc::now());
+    let config = base.with_profile(profile);
+    let board = source::create(&config.source_config()?, config.timezone, None).fetch()?;
+
+    Ok((config, profile, board))
+}
+
+/// Prints the departures that would be on the board, one per line.
+fn print_departures(opt: &Opt, command: &FetchCommand) -> Result<()> {
+    let (config, _, mut board) = fetch_board(opt)?;
+
+    let filter = Filter {
+        include: config.filters.include.clone(),
+        exclude: config.filters.exclude.clone(),
+    };
+    let modes = config.filters.visible_modes();
+    board.departures.retain(|departure| {
+        filter.matches(departure) && modes.contains(&departure.transport_mode)
+    });
+    board
+        .alerts
+        .retain(|alert| alert.transport_mode.is_none_or(|mode| modes.contains(&mode)));
+
+    if command.json {
+        println!("{}", serde_json::to_string_pretty(&board)?);
+        return Ok(());
+    }
+
+    if board.departures.is_empty() {
+        println!("No departures");
+    }
+    for departure in &board.departures {
+        let delay = (departure.expected - departure.scheduled).num_minutes();
+        let status = if departure.cancelled {
+            "Inställd".to_string()
+        } else if delay != 0 {
+            format!("{:+}", delay)
+        } else {
+            String::new()
+        };
+        let line = format!(
+            "{}  {:<5} {:<5} {:<24} {:<20} {}",
+            departure.expected.with_timezone(&config.timezone).format("%H:%M"),
+            departure.transport_mode.name(),
+            departure.line,
+            departure.destination,
+            departure.stop_area_name,
+            status,
+        );
+        println!("{}", line.trim_end());
+    }
+
+    for text in departure::distinct_texts(board.alerts.iter().map(|alert| &alert.deviation)) {
+        println!("! {}", text);
+    }
+
+    Ok(())
+}
+
+/// Draws the board as it would look on the display right now, to a png.
+fn render_board(opt: &Opt, command: &RenderCommand) -> Result<()> {
+    let (config, profile, board) = fetch_board(opt)?;
+    let now = Utc::now();
+
+    // The display is turned on its side
+    let mut buffer = buffer::Buffer::<[u8; 3]>::new(command.height, command.width);
+    let mut screen = Screen::new(
+        &config,
+        profile_name(&config, profile),
+        command.width,
+        command.height,
+    );
+    screen.show(&board);
+    screen.update(
+        now,
+        screen::staleness_label(chrono::Duration::seconds(board.data_age.into())),
+    );
+    screen.render(&mut buffer);
+    buffer.save_png(&command.output)?;
+
+    println!("Wrote {}", command.output);
+    Ok(())
+}
+
+/// Saves the responses from the configured source, for the profile that
+/// applies at the time, as often as the board would fetch them.
+fn record_responses(opt: &Opt, command: &RecordCommand) -> Result<()> {
+    let base = load_config(opt)?;
+    base.validate()?;
+    // Profiles change the stations, but never the source
+    if !matches!(base.source.as_str(), "sl" | "sl-transport" | "siri") {
+        return Err(format!("the {} source can't be recorded", base.source).into());
+    }
+
+    let api_key = if command.scrub_api_key { base.api_key.clone() } else { None };
+    let recorder = Recorder::new(&command.recording, base.timezone, api_key)?;
+    let mut schedule = poll_schedule(&base.polling, base.timezone, 0);
+    loop {
+        let now = Utc::now();
+        let source_config = base.with_profile(base.profile_at(now)).source_config()?;
+        // The profile may have switched to other stations
+        schedule.calls_per_fetch = source_config.api_calls();
+
+        match recorder.record(&source_config, now) {
+            Ok(paths) => {
+                for path in paths {
+                    println!("Saved {}", path.display());
+                }
+            }
+            Err(e) => eprintln!("Failed to record departures: {}", e),
+        }
+
+        std::thread::sleep(schedule.next(Utc::now()));
+    }
+}
+
+/// Polls as set up by `polling`, for a source that makes `calls_per_fetch`
+/// api calls each time.
+fn poll_schedule(polling: &PollingConfig, tz: Tz, calls_per_fetch: u32) -> PollSchedule {
+    PollSchedule {
+        tz,
+        commute: polling.commute.clone(),
+        night: polling.night.clone(),
+        commute_interval: std::time::Duration::from_secs(polling.refresh_interval),
+        idle_interval: std::time::Duration::from_secs(polling.idle_interval),
+        calls_per_fetch,
+        budget: polling
+            .monthly_budget
+            .map(|monthly| Budget::load(&polling.budget_file, monthly)),
+    }
+}
+
+/// Checks that the board can be set up with `config`. Replays don't use the
+/// configured source, so its settings are left alone.
+fn validate(config: &Config, replaying: bool) -> Result<()> {
+    if replaying {
+        config.validate_settings()
     } else {
-        dbg!(fetch_from_server(&opt.api_key, opt.station_id)?)
+        config.validate()
+    }
+}
+
+/// Takes over the display and shows the board until stopped, or the
+/// recording in `replay` instead of fetched departures.
+fn show_board(opt: &Opt, replay: Option<&ReplayCommand>) -> Result<()> {
+    let (clock, recording) = match replay {
+        Some(replay) => {
+            if replay.speed <= 0.0 {
+                return Err("--speed must be above zero".into());
+            }
+            let recording = Recording::open(&replay.recording)?;
+            let start = recording.frames[0].fetched_at;
+            let end = recording.frames[recording.frames.len() - 1].fetched_at;
+            (Clock::simulated(start, end, replay.speed), Some(recording))
+        }
+        None => (Clock::System, None),
+    };
+
+    let mut config = load_config(opt)?;
+    // Check before taking over the display
+    validate(&config, recording.is_some())?;
+
+    // What was saved from the live source belongs to another time than the
+    // recording
+    let saved = match recording {
+        Some(_) => Err("replaying a recording".into()),
+        None => state::load(&config.state_file),
+    };
+    let snapshot = match saved {
+        Ok(snapshot) => Some(snapshot),
+        Err(e) => {
+            eprintln!("No previous departures loaded from {}: {}", config.state_file, e);
+            None
+        }
+    };
+    let mut session = Session {
+        snapshot,
+        timetable: None,
+        fetcher: None,
+    };
+
+    let mut reload = ReloadTrigger::new(config_path(opt))?;
+    let mut hw = create_hardware(&config.hardware)?;
+    loop {
+        config = run(
+            opt,
+            config,
+            clock,
+            recording.as_ref(),
+            &mut session,
+            &mut hw,
+            &mut reload,
+        )?;
+    }
+}
+
+/// What the fetcher is started with. The same fetcher keeps running as long
+/// as these stay the same, since starting another costs a fetch.
+#[derive(PartialEq)]
+struct FetchSettings {
+    source: SourceConfig,
+    polling: PollingConfig,
+    tz: Tz,
+    state_file: String,
+    /// Minutes
+    delay_threshold: i64,
+}
+
+/// What carries over when the config is reloaded, each part rebuilt only
+/// when the settings it comes from change.
+struct Session {
+    /// The latest departures
+    snapshot: Option<Snapshot>,
+    /// Along with the settings it was loaded with. Shared with the source,
+    /// if it needs one too
+    timetable: Option<(GtfsConfig, Option<Arc<gtfs::Timetable>>)>,
+    fetcher: Option<(FetchSettings, Fetcher<Snapshot>)>,
+}
+
+impl Session {
+    /// Loads the timetable and starts the fetcher for `config`, unless
+    /// they're already in place. Departures come from `recording` instead
+    /// of the configured source if there is one.
+    fn apply(
+        &mut self,
+        config: &Config,
+        clock: Clock,
+        recording: Option<&Recording>,
+    ) -> Result<()> {
+        let timetable_current = self
+            .timetable
+            .as_ref()
+            .is_some_and(|(gtfs, _)| *gtfs == config.gtfs);
+        if !timetable_current {
+            self.timetable = Some((config.gtfs.clone(), load_timetable(&config.gtfs)));
+        }
+
+        let source = match recording {
+            Some(recording) => SourceConfig::Replay {
+                recording: recording.clone(),
+                walk_times: config
+                    .stations
+                    .iter()
+                    .map(|station| (station.id, station.walk_time))
+                    .collect(),
+            },
+            None => config.source_config()?,
+        };
+        let settings = FetchSettings {
+            source,
+            polling: config.polling.clone(),
+            tz: config.timezone,
+            state_file: config.state_file.clone(),
+            delay_threshold: config.delays.threshold,
+        };
+        let fetcher_current = self
+            .fetcher
+            .as_ref()
+            .is_some_and(|(current, _)| *current == settings);
+        if !fetcher_current {
+            let timetable = self.timetable().cloned();
+            let fetcher = spawn_fetcher(&settings, timetable, clock);
+            self.fetcher = Some((settings, fetcher));
+        }
+
+        Ok(())
+    }
+
+    /// The latest result from the fetcher, if there's been one since the
+    /// last poll.
+    fn poll(&self) -> Option<Result<Snapshot>> {
+        self.fetcher.as_ref().and_then(|(_, fetcher)| fetcher.poll())
+    }
+
+    fn wake(&self) {
+        if let Some((_, fetcher)) = &self.fetcher {
+            fetcher.wake();
+        }
+    }
+
+    fn timetable(&self) -> Option<&Arc<gtfs::Timetable>> {
+        self.timetable.as_ref().and_then(|(_, timetable)| timetable.as_ref())
+    }
+}
+
+/// Loads the timetable to fall back on, if one is set up. Loading is slow
+/// for large feeds, but there's nothing better to show in the meantime.
+fn load_timetable(gtfs: &GtfsConfig) -> Option<Arc<gtfs::Timetable>> {
+    let path = match &gtfs.static_path {
+        Some(path) if !gtfs.stops.is_empty() => path,
+        _ => return None,
+    };
+
+    match gtfs::Timetable::load(path, &gtfs.stops) {
+        Ok(timetable) => Some(Arc::new(timetable)),
+        Err(e) => {
+            eprintln!("Failed to load timetable from {}: {}", path, e);
+            None
+        }
+    }
+}
+
+/// Starts fetching departures as set up by `settings`, on `clock`, with the
+/// `timetable` if it's been loaded.
+fn spawn_fetcher(
+    settings: &FetchSettings,
+    timetable: Option<Arc<gtfs::Timetable>>,
+    clock: Clock,
+) -> Fetcher<Snapshot> {
+    // Recordings are picked up where the clock is
+    let source_config = match &settings.source {
+        SourceConfig::Replay {
+            recording,
+            walk_times,
+        } => SourceConfig::Replay {
+            recording: recording.since(clock.now()),
+            walk_times: walk_times.clone(),
+        },
+        source_config => source_config.clone(),
+    };
+    let tz = settings.tz;
+    let mut source = source::create(&source_config, tz, timetable);
+    let state_file = settings.state_file.clone();
+    let mut tracker = JourneyTracker::new(chrono::Duration::minutes(settings.delay_threshold));
+    let schedule: Box<dyn FnMut() -> Option<std::time::Duration> + Send> = match &source_config {
+        SourceConfig::Replay { recording, .. } => {
+            let mut schedule = ReplaySchedule::new(clock, recording);
+            Box::new(move || schedule.until_next())
+        }
+        _ => {
+            let mut schedule = poll_schedule(&settings.polling, tz, source_config.api_calls());
+            Box::new(move || Some(schedule.next(clock.now())))
+        }
+    };
+    Fetcher::spawn(schedule, move || {
+        let fetched_at = clock.now();
+        let snapshot = Snapshot {
+            fetched_at,
+            board: tracker.track(source.fetch()?, fetched_at),
+        };
+
+        // Recorded data would just shadow the real thing on the next start
+        if source_config.is_live() {
+            if let Err(e) = state::store(&state_file, &snapshot) {
+                eprintln!("Failed to save departures to {}: {}", state_file, e);
+            }
+        }
+
+        Ok(snapshot)
+    })
+}
+
+/// The timetable while it stands in for realtime data, otherwise the latest
+/// fetched board.
+fn shown_board<'a>(
+    scheduled: &'a Option<(DateTime<Utc>, Board)>,
+    session: &'a Session,
+) -> Option<&'a Board> {
+    scheduled
+        .as_ref()
+        .map(|(_, board)| board)
+        .or_else(|| session.snapshot.as_ref().map(|snapshot| &snapshot.board))
+}
+
+/// Shows the board set up by `base`, with whichever of its profiles applies,
+/// until a valid config is reloaded. Returns the reloaded config. Departures
+/// come from `recording` instead of the configured source if there is one.
+fn run<P, H>(
+    opt: &Opt,
+    base: Config,
+    clock: Clock,
+    recording: Option<&Recording>,
+    session: &mut Session,
+    hw: &mut H,
+    reload: &mut ReloadTrigger,
+) -> Result<Config>
+where
+    P: PixelType + 'static,
+    H: Hardware<P>,
+{
+    let mut profile_checked_at = clock.now();
+    let mut profile = base.profile_at(profile_checked_at);
+    let mut config = base.with_profile(profile);
+    session.apply(&config, clock, recording)?;
+    let tz = config.timezone;
+    let fallback_after = chrono::Duration::seconds(config.fallback_after);
+
+    // Recordings of night service are there to be looked at
+    let night = match recording {
+        Some(_) => vec![],
+        None => config.polling.night.clone(),
     };
 
-    let mut hw = create_hardware()?;
     let mut buffer = buffer::Buffer::new(hw.xres(), hw.yres());
+    // Shown instead while the display sleeps, so that the board is still
+    // there to flip back to when it wakes up
+    let blank = buffer::Buffer::new(hw.xres(), hw.yres());
 
-    let mut outer_layout = ScrollGadget::new(buffer.width(), buffer.height());
-    let clock = Rc::new(TextGadget::new(
-        format!("{}", chrono::Local::now().time()),
+    // The display is turned on its side
+    let mut screen = Screen::new(
+        &config,
+        profile_name(&base, profile),
+        buffer.height(),
         buffer.width(),
-        32,
-        [1.0, 1.0, 1.0],
-        32.0,
-    ));
-    outer_layout.children.push(clock.clone());
-
-    let mut inner_layout = ScrollGadget::new(buffer.height(), buffer.width());
-    for (i, departure) in data.metros.iter().enumerate() {
-        inner_layout.children.push(
-            Rc::new(TextGadget::new(
-                format!("{} {} {}", i, departure.destination, departure.display_time),
-                100,
-                32,
-                [1.0, 1.0, 0.0],
-                32.0,
-            )),
-        );
+    );
 
-        //let mut container = HorizontalGadget::new(buffer.height(), 32);
-
-        //container.children.push(
-        //    Rc::new(TextGadget::new(
-        //        "foo".to_string(), // format!("{} {}", i, departure.destination),
-        //        100,
-        //        32,
-        //        [1.0, 1.0, 0.0],
-        //        32.0,
-        //    )),
-        //);
-
-        //container.children.push(
-        //    Rc::new(TextGadget::new(
-        //        "bar".to_string(), // format!("{}", departure.display_time),
-        //        50,
-        //        32,
-        //        [1.0, 1.0, 0.0],
-        //        32.0,
-        //    )),
-        //);
-
-        //inner_layout.children.push(Rc::new(container));
-    }
-    let inner_layout = Rc::new(inner_layout);
-    outer_layout.children.push(inner_layout.clone());
-
-    let mut scroll: isize = 0;
+    let mut scheduled: Option<(DateTime<Utc>, Board)> = None;
+    let mut update_rows = session.snapshot.is_some();
+    let mut awake_until: Option<DateTime<Utc>> = None;
+    let mut sleeping = false;
     loop {
-        clock.text(format!("{}", chrono::Local::now().time().format("%H:%M:%S")));
+        if reload.triggered() {
+            let reloaded = load_config(opt).and_then(|reloaded| {
+                validate(&reloaded, recording.is_some())?;
+                Ok(reloaded)
+            });
+            match reloaded {
+                Ok(reloaded) => {
+                    if reloaded.hardware != config.hardware {
+                        eprintln!("Hardware settings take effect after a restart");
+                    }
+                    eprintln!("Reloaded the config");
+                    return Ok(reloaded);
+                }
+                Err(e) => eprintln!("Keeping the current config, the new one is invalid: {}", e),
+            }
+        }
+
+        let now = clock.now();
+        if now - profile_checked_at >= chrono::Duration::minutes(1) {
+            profile_checked_at = now;
+            if base.profile_at(now) != profile {
+                // The fetcher keeps going unless the stations changed
+                profile = base.profile_at(now);
+                config = base.with_profile(profile);
+                session.apply(&config, clock, recording)?;
+                screen = Screen::new(
+                    &config,
+                    profile_name(&base, profile),
+                    buffer.height(),
+                    buffer.width(),
+                );
+                update_rows = true;
+            }
+        }
 
-        let y_offset = 40;
-        let line_size = 32;
-        let per_page = (hw.xres() - y_offset) / line_size;
-        let max_scroll_pos = (data.metros.len() as isize - per_page as isize).max(0);
+        match session.poll() {
+            Some(Ok(fresh_snapshot)) => {
+                session.snapshot = Some(fresh_snapshot);
+                update_rows = true;
+            }
+            Some(Err(e)) => eprintln!("Failed to fetch departures: {}", e),
+            None => {}
+        }
+
+        // Show the timetable while realtime data is missing or too old,
+        // refreshed every minute so that later departures come into view
+        let realtime_ok = session
+            .snapshot
+            .as_ref()
+            .is_some_and(|snapshot| snapshot.age(now) <= fallback_after);
+        if realtime_ok {
+            if scheduled.take().is_some() {
+                update_rows = true;
+            }
+        } else if let Some(timetable) = session.timetable() {
+            let current = scheduled.as_ref().is_some_and(|(computed_at, _)| {
+                now - *computed_at < chrono::Duration::minutes(1)
+            });
+            if !current {
+                let board =
+                    timetable.scheduled_board(now, chrono::Duration::hours(1), tz);
+                scheduled = Some((now, board));
+                update_rows = true;
+            }
+        }
+
+        let stale = match (shown_board(&scheduled, session), &session.snapshot) {
+            (Some(board), _) if board.scheduled_only => "scheduled only".to_string(),
+            (_, Some(snapshot)) => screen::staleness_label(snapshot.age(now)),
+            (_, None) => "no data".to_string(),
+        };
+
+        let mut events = hw.poll_events()?;
+        let night = night
+            .iter()
+            .any(|range| range.contains(now.with_timezone(&tz).time()));
+        let asleep = night && awake_until.is_none_or(|until| now >= until);
+        if asleep && !events.is_empty() {
+            // Wake up rather than act on whatever woke us
+            events.clear();
+            awake_until = Some(now + chrono::Duration::minutes(5));
+            session.wake();
+        } else if asleep {
+            if !sleeping {
+                hw.flip(&blank)?;
+                sleeping = true;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(1000 / 60));
+            continue;
+        }
+        if sleeping {
+            hw.flip(&buffer)?;
+            sleeping = false;
+        }
 
-        let events = hw.poll_events()?;
         for event in events {
             match event {
-                HwEvent::Scroll(delta) => {
-                    let updated_scroll = scroll + delta;
-                    if updated_scroll < 0 {
-                        scroll = 0;
-                    } else if updated_scroll > max_scroll_pos {
-                        scroll = max_scroll_pos;
-                    } else {
-                        scroll = updated_scroll;
-                    }
-                    dbg!(scroll);
-                    inner_layout.scroll(scroll as usize);
+                HwEvent::Scroll(delta) => screen.scroll(delta),
+                HwEvent::Button => {
+                    screen.next_mode();
+                    update_rows = true;
                 }
             }
         }
 
+        if update_rows {
+            if let Some(board) = shown_board(&scheduled, session) {
+                screen.show(board);
+            }
+            update_rows = false;
+        }
+        screen.update(now, stale);
+
         let start = std::time::Instant::now();
-        let rect = RenderRect {
-            x: 0,
-            y: 0,
-            width: buffer.height(),
-            height: buffer.width(),
-        };
-        if outer_layout.dirty() {
-            outer_layout.render(rect, &mut buffer);
+        if screen.render(&mut buffer) {
             hw.flip(&buffer)?;
             let end = std::time::Instant::now();
             println!("Rendered frame in {}ms", (end - start).as_millis());
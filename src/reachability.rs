@@ -0,0 +1,134 @@
+use std::collections::HashMap;
+
+use chrono::{DateTime, Duration, Utc};
+
+use crate::departure::Departure;
+
+/// How long it takes to get to the departures, from wherever the board
+/// hangs.
+#[derive(Debug, Clone, Default)]
+pub struct WalkTimes {
+    /// Minutes, for stop points without a walk time of their own
+    pub default: u32,
+    /// Minutes, by stop point id or designation
+    pub stop_points: HashMap<String, u32>,
+}
+
+impl WalkTimes {
+    /// Minutes it takes to walk to `departure`. Stop points configured here
+    /// take precedence over the site's walk time on merged boards, which in
+    /// turn takes precedence over the default.
+    pub fn walk_time(&self, departure: &Departure) -> u32 {
+        let stop_point = self.stop_points.get(&departure.stop_point_id).or_else(|| {
+            departure
+                .stop_point_designation
+                .as_ref()
+                .and_then(|designation| self.stop_points.get(designation))
+        });
+
+        match stop_point {
+            Some(walk_time) => *walk_time,
+            None if departure.walk_time > 0 => departure.walk_time,
+            None => self.default,
+        }
+    }
+}
+
+/// A walk time for a single stop point, written as `stop=minutes`.
+#[derive(Debug, Clone)]
+pub struct StopPointWalkTime {
+    pub stop_point: String,
+    pub walk_time: u32,
+}
+
+impl std::str::FromStr for StopPointWalkTime {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("expected <stop>=<walk minutes>: {}", s);
+        let pos = s.find('=').ok_or_else(invalid)?;
+
+        Ok(StopPointWalkTime {
+            stop_point: s[..pos].trim().to_string(),
+            walk_time: s[pos + 1..].trim().parse().map_err(|_| invalid())?,
+        })
+    }
+}
+
+/// Whether there's time to catch a departure.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Reachability {
+    Relaxed,
+    /// Leave now, at a brisk walk
+    Hurry,
+    /// Only by running
+    Run,
+    Unreachable,
+}
+
+impl Reachability {
+    pub fn of(expected: DateTime<Utc>, walk_time: u32, now: DateTime<Utc>) -> Reachability {
+        // Already at the platform
+        if walk_time == 0 {
+            return Reachability::Relaxed;
+        }
+
+        let walk_time = Duration::minutes(walk_time.into());
+        let slack = expected - walk_time - now;
+        if slack >= Duration::minutes(2) {
+            Reachability::Relaxed
+        } else if slack >= Duration::zero() {
+            Reachability::Hurry
+        } else if slack >= -walk_time / 2 {
+            // Running takes about half as long as walking
+            Reachability::Run
+        } else {
+            Reachability::Unreachable
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn departure(stop_point_id: &str, designation: Option<&str>, walk_time: u32) -> Departure {
+        Departure {
+            stop_point_id: stop_point_id.to_string(),
+            stop_point_designation: designation.map(str::to_string),
+            walk_time,
+            ..Departure::example()
+        }
+    }
+
+    #[test]
+    fn stop_points_take_precedence_over_stations() {
+        let walk_times = WalkTimes {
+            default: 2,
+            stop_points: vec![("1012".to_string(), 4), ("B".to_string(), 6)]
+                .into_iter()
+                .collect(),
+        };
+
+        assert_eq!(walk_times.walk_time(&departure("1012", Some("B"), 8)), 4);
+        assert_eq!(walk_times.walk_time(&departure("1013", Some("B"), 8)), 6);
+        assert_eq!(walk_times.walk_time(&departure("1013", Some("C"), 8)), 8);
+        assert_eq!(walk_times.walk_time(&departure("1013", None, 0)), 2);
+    }
+
+    #[test]
+    fn reachability_boundaries() {
+        let now = Utc::now();
+        let expected = now + Duration::minutes(10);
+        let of = |walk_time| Reachability::of(expected, walk_time, now);
+
+        assert_eq!(of(0), Reachability::Relaxed);
+        assert_eq!(of(8), Reachability::Relaxed);
+        assert_eq!(of(9), Reachability::Hurry);
+        assert_eq!(of(10), Reachability::Hurry);
+        assert_eq!(of(11), Reachability::Run);
+        // Half of the 20 minute walk can be made up by running
+        assert_eq!(of(20), Reachability::Run);
+        assert_eq!(of(21), Reachability::Unreachable);
+    }
+}
@@ -0,0 +1,60 @@
+use std::ffi::OsString;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use inotify::{Inotify, WatchMask};
+
+use crate::Result;
+
+/// Tells when the config should be read again: after a SIGHUP, or once the
+/// config file has been written to.
+pub struct ReloadTrigger {
+    hangup: Arc<AtomicBool>,
+    inotify: Inotify,
+    /// The config file's name within the watched directory
+    file_name: OsString,
+    buffer: [u8; 1024],
+}
+
+impl ReloadTrigger {
+    pub fn new(config_path: &Path) -> Result<ReloadTrigger> {
+        let hangup = Arc::new(AtomicBool::new(false));
+        signal_hook::flag::register(signal_hook::consts::SIGHUP, hangup.clone())?;
+
+        // Editors tend to save by writing a new file and renaming it over
+        // the old one, which a watch on the file itself wouldn't survive
+        let dir = match config_path.parent() {
+            Some(dir) if dir != Path::new("") => dir,
+            _ => Path::new("."),
+        };
+        let mut inotify = Inotify::init()?;
+        inotify.add_watch(dir, WatchMask::CLOSE_WRITE | WatchMask::MOVED_TO)?;
+
+        Ok(ReloadTrigger {
+            hangup,
+            inotify,
+            file_name: config_path.file_name().unwrap_or_default().to_os_string(),
+            buffer: [0; 1024],
+        })
+    }
+
+    /// Whether a reload has been asked for since the last call. Doesn't
+    /// block.
+    pub fn triggered(&mut self) -> bool {
+        let hangup = self.hangup.swap(false, Ordering::Relaxed);
+
+        let file_name = &self.file_name;
+        let written = match self.inotify.read_events(&mut self.buffer) {
+            Ok(events) => events
+                .into_iter()
+                .any(|event| event.name == Some(file_name.as_os_str())),
+            Err(e) => {
+                eprintln!("Failed to watch the config file: {}", e);
+                false
+            }
+        };
+
+        hangup || written
+    }
+}
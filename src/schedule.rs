@@ -0,0 +1,338 @@
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use chrono::{DateTime, Datelike, NaiveDate, NaiveTime, Utc};
+use chrono_tz::Tz;
+use serde_derive::{Deserialize, Serialize};
+
+use crate::{clock::Clock, source::replay::Recording, time, Result};
+
+/// A part of the day, written as 07:00-09:30. Ranges may wrap past
+/// midnight, as in 23:30-05:00.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TimeRange {
+    pub start: NaiveTime,
+    pub end: NaiveTime,
+}
+
+impl TimeRange {
+    pub fn contains(&self, time: NaiveTime) -> bool {
+        if self.start <= self.end {
+            self.start <= time && time < self.end
+        } else {
+            self.start <= time || time < self.end
+        }
+    }
+}
+
+impl std::str::FromStr for TimeRange {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let invalid = || format!("expected a time range such as 07:00-09:30: {}", s);
+        let pos = s.find('-').ok_or_else(invalid)?;
+        let parse = |time: &str| NaiveTime::parse_from_str(time.trim(), "%H:%M").map_err(|_| invalid());
+
+        Ok(TimeRange {
+            start: parse(&s[..pos])?,
+            end: parse(&s[pos + 1..])?,
+        })
+    }
+}
+
+/// API calls made so far this month, kept on disk so that restarts don't
+/// reset the count.
+#[derive(Debug, Default, Deserialize, Serialize)]
+struct Usage {
+    /// ex: 2024-03
+    month: String,
+    calls: u32,
+}
+
+/// A monthly allowance of API calls, such as the quota of a Trafiklab key.
+pub struct Budget {
+    monthly: u32,
+    path: PathBuf,
+    usage: Usage,
+}
+
+impl Budget {
+    pub fn load<P: AsRef<Path>>(path: P, monthly: u32) -> Budget {
+        let path = path.as_ref().to_path_buf();
+        let usage = std::fs::read_to_string(&path)
+            .ok()
+            .and_then(|x| serde_json::from_str(&x).ok())
+            .unwrap_or_default();
+
+        Budget {
+            monthly,
+            path,
+            usage,
+        }
+    }
+
+    fn record(&mut self, calls: u32, month: String) -> Result<()> {
+        if self.usage.month != month {
+            self.usage = Usage { month, calls: 0 };
+        }
+        self.usage.calls += calls;
+
+        std::fs::write(&self.path, serde_json::to_string(&self.usage)?)?;
+        Ok(())
+    }
+
+    fn remaining(&self) -> u32 {
+        self.monthly.saturating_sub(self.usage.calls)
+    }
+}
+
+/// Decides how often to poll: often during commute hours, less often the
+/// rest of the day and not at all at night, slowing down further if that
+/// would run through the monthly budget before the month is over.
+pub struct PollSchedule {
+    pub tz: Tz,
+    pub commute: Vec<TimeRange>,
+    pub night: Vec<TimeRange>,
+    /// Between polls during commute hours, or all day if there are none
+    pub commute_interval: Duration,
+    pub idle_interval: Duration,
+    /// API calls a single fetch makes, which is more than one for merged
+    /// boards
+    pub calls_per_fetch: u32,
+    pub budget: Option<Budget>,
+}
+
+impl PollSchedule {
+    /// How often we'd like to poll at `time`, or `None` for not at all.
+    fn planned_interval(&self, time: DateTime<Utc>) -> Option<Duration> {
+        let local = time.with_timezone(&self.tz).time();
+        if self.night.iter().any(|range| range.contains(local)) {
+            None
+        } else if self.commute.is_empty() || self.commute.iter().any(|range| range.contains(local)) {
+            Some(self.commute_interval)
+        } else {
+            Some(self.idle_interval)
+        }
+    }
+
+    /// When the night that `now` falls in is over, checked minute by minute
+    /// for up to a day.
+    fn night_end(&self, now: DateTime<Utc>) -> DateTime<Utc> {
+        let step = chrono::Duration::minutes(1);
+        let mut time = now;
+        while self.planned_interval(time).is_none() && time - now < chrono::Duration::days(1) {
+            time = time + step;
+        }
+        time
+    }
+
+    /// When the first day of next month begins.
+    fn next_month(&self, now: DateTime<Utc>) -> DateTime<Utc> {
+        let today = time::local_date(self.tz, now);
+        let next_month = if today.month() == 12 {
+            NaiveDate::from_ymd(today.year() + 1, 1, 1)
+        } else {
+            NaiveDate::from_ymd(today.year(), today.month() + 1, 1)
+        };
+
+        time::from_local(self.tz, next_month.and_hms(0, 0, 0), now)
+    }
+
+    /// How many calls polling as planned would make for the rest of the
+    /// month, sampled every few minutes.
+    fn planned_calls(&self, now: DateTime<Utc>) -> f64 {
+        let step = chrono::Duration::minutes(5);
+        let end = self.next_month(now);
+
+        let mut calls = 0.0;
+        let mut time = now;
+        while time < end {
+            if let Some(interval) = self.planned_interval(time) {
+                calls += step.num_seconds() as f64 / interval.as_secs_f64();
+            }
+            time = time + step;
+        }
+
+        calls * f64::from(self.calls_per_fetch)
+    }
+
+    /// Records that a fetch was just made, and returns how long to wait
+    /// until the next one.
+    pub fn next(&mut self, now: DateTime<Utc>) -> Duration {
+        let month = now.with_timezone(&self.tz).format("%Y-%m").to_string();
+        if let Some(budget) = &mut self.budget {
+            if let Err(e) = budget.record(self.calls_per_fetch, month) {
+                eprintln!("Failed to save API usage to {}: {}", budget.path.display(), e);
+            }
+        }
+
+        let interval = match self.planned_interval(now) {
+            Some(interval) => interval,
+            // Nothing is fetched at night, unless the display is woken up,
+            // which is also why the night is left out of the budget
+            None => {
+                let until_morning = (self.night_end(now) - now).to_std();
+                return until_morning.unwrap_or(self.idle_interval);
+            }
+        };
+
+        let budget = match &self.budget {
+            Some(budget) => budget,
+            None => return interval,
+        };
+
+        let remaining = budget.remaining();
+        let interval = if remaining == 0 {
+            (self.next_month(now) - now).to_std().unwrap_or(interval)
+        } else {
+            // Stretch every interval by the same factor, so that the
+            // commute hours keep polling faster than the rest of the day
+            let planned = self.planned_calls(now);
+            interval.mul_f64((planned / f64::from(remaining)).max(1.0))
+        };
+
+        eprintln!(
+            "Used {} of {} API calls this month, next fetch in {}s",
+            budget.usage.calls,
+            budget.monthly,
+            interval.as_secs(),
+        );
+
+        interval
+    }
+}
+
+/// Fetches in step with a recording, whenever the clock reaches the time
+/// the next frame was recorded at.
+pub struct ReplaySchedule {
+    clock: Clock,
+    /// Of the frames after the one fetched first
+    times: std::vec::IntoIter<DateTime<Utc>>,
+}
+
+impl ReplaySchedule {
+    pub fn new(clock: Clock, recording: &Recording) -> ReplaySchedule {
+        let times: Vec<_> = recording.frames.iter().skip(1).map(|x| x.fetched_at).collect();
+        ReplaySchedule {
+            clock,
+            times: times.into_iter(),
+        }
+    }
+
+    /// How long to wait before fetching the next frame, or None once
+    /// they've all been fetched.
+    pub fn until_next(&mut self) -> Option<Duration> {
+        let time = self.times.next();
+        if time.is_none() {
+            eprintln!("Reached the end of the recording");
+        }
+        time.map(|time| self.clock.until(time))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("triportreat-{}-{}.json", name, std::process::id()))
+    }
+
+    fn schedule(budget: Option<Budget>) -> PollSchedule {
+        PollSchedule {
+            tz: chrono_tz::Europe::Stockholm,
+            commute: vec![],
+            night: vec!["00:30-05:30".parse().unwrap()],
+            commute_interval: Duration::from_secs(60),
+            idle_interval: Duration::from_secs(300),
+            calls_per_fetch: 1,
+            budget,
+        }
+    }
+
+    #[test]
+    fn time_ranges_wrap_past_midnight() {
+        let range: TimeRange = "23:30-05:00".parse().unwrap();
+        let at = |time: &str| range.contains(NaiveTime::parse_from_str(time, "%H:%M").unwrap());
+        assert!(at("23:30"));
+        assert!(at("00:00"));
+        assert!(at("04:59"));
+        assert!(!at("05:00"));
+        assert!(!at("12:00"));
+
+        let range: TimeRange = "07:00-09:30".parse().unwrap();
+        assert!(range.contains(NaiveTime::from_hms(7, 0, 0)));
+        assert!(!range.contains(NaiveTime::from_hms(9, 30, 0)));
+        assert!("07:00".parse::<TimeRange>().is_err());
+    }
+
+    #[test]
+    fn budget_starts_over_each_month() {
+        let path = temp_path("usage");
+        let mut budget = Budget::load(&path, 1000);
+        assert_eq!(budget.remaining(), 1000);
+
+        budget.record(900, "2024-02".to_string()).unwrap();
+        assert_eq!(budget.remaining(), 100);
+        assert_eq!(Budget::load(&path, 1000).remaining(), 100);
+
+        budget.record(2, "2024-03".to_string()).unwrap();
+        assert_eq!(budget.remaining(), 998);
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn sleeps_through_the_night() {
+        // 01:00 in Stockholm
+        let now = "2024-03-12T00:00:00Z".parse().unwrap();
+        let mut schedule = schedule(None);
+        assert!(schedule.planned_interval(now).is_none());
+        assert_eq!(schedule.next(now), Duration::from_secs(4 * 60 * 60 + 30 * 60));
+    }
+
+    #[test]
+    fn stretches_intervals_to_last_the_month() {
+        // Noon on the last day of March, with twelve hours to go
+        let now: DateTime<Utc> = "2024-03-31T10:00:00Z".parse().unwrap();
+        let path = temp_path("stretch");
+        let budget = Budget {
+            monthly: 1000,
+            path: path.clone(),
+            usage: Usage {
+                month: "2024-03".to_string(),
+                calls: 639,
+            },
+        };
+        let mut schedule = schedule(Some(budget));
+        assert_eq!(schedule.planned_calls(now).round() as u32, 720);
+
+        // 360 calls left for the 720 planned ones
+        assert_eq!(schedule.next(now).as_secs(), 120);
+
+        // And none left at all
+        schedule.budget.as_mut().unwrap().usage.calls = 999;
+        let until_april = "2024-03-31T22:00:00Z".parse::<DateTime<Utc>>().unwrap() - now;
+        assert_eq!(schedule.next(now), until_april.to_std().unwrap());
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn replays_stop_at_the_end() {
+        let recording = Recording::open("./test/data/recording").unwrap();
+        let start = recording.frames[0].fetched_at;
+        let end = recording.frames[1].fetched_at;
+        let clock = Clock::simulated(start, end, 600.0);
+
+        // Two minutes between the frames, at ten minutes a second
+        let mut schedule = ReplaySchedule::new(clock, &recording);
+        let wait = schedule.until_next().unwrap();
+        assert!(wait <= Duration::from_millis(200) && wait > Duration::from_millis(100));
+        assert_eq!(schedule.until_next(), None);
+
+        // The clock stops along with the recording
+        std::thread::sleep(wait);
+        assert_eq!(clock.now(), end);
+    }
+}
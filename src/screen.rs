@@ -0,0 +1,624 @@
+use std::rc::Rc;
+
+use chrono::{DateTime, Utc};
+use chrono_tz::Tz;
+
+use crate::{
+    buffer::{Buffer, PixelType, Rgb},
+    config::{Config, Layout, Theme},
+    departure::{self, Board, Change, TransportMode},
+    filter::Filter,
+    gadget::{self, Gadget, HorizontalGadget, RenderRect, ScrollGadget, TextGadget},
+    reachability::{Reachability, WalkTimes},
+};
+
+fn mode_visible(
+    mode: TransportMode,
+    modes: &[TransportMode],
+    selected: Option<TransportMode>,
+) -> bool {
+    match selected {
+        Some(selected) => mode == selected,
+        None => modes.contains(&mode),
+    }
+}
+
+/// Formats the time left until `expected` the way the platform signs do,
+/// or `None` if the departure has already left.
+fn countdown_label(expected: DateTime<Utc>, now: DateTime<Utc>, tz: Tz) -> Option<String> {
+    let left = expected - now;
+    if left < chrono::Duration::zero() {
+        None
+    } else if left < chrono::Duration::minutes(1) {
+        Some("Nu".to_string())
+    } else if left < chrono::Duration::minutes(30) {
+        Some(format!("{} min", left.num_minutes()))
+    } else {
+        Some(expected.with_timezone(&tz).format("%H:%M").to_string())
+    }
+}
+
+/// How to pick and present the departures on the board.
+struct RowConfig {
+    filter: Filter,
+    walk_times: WalkTimes,
+    delays: DelayThresholds,
+    tz: Tz,
+    layout: Layout,
+    theme: Theme,
+}
+
+impl RowConfig {
+    fn new(config: &Config) -> RowConfig {
+        RowConfig {
+            filter: Filter {
+                include: config.filters.include.clone(),
+                exclude: config.filters.exclude.clone(),
+            },
+            walk_times: WalkTimes {
+                default: config.walk_time,
+                stop_points: config.stop_walk_times.clone(),
+            },
+            delays: DelayThresholds {
+                shown: chrono::Duration::minutes(config.delays.threshold),
+                severe: chrono::Duration::minutes(config.delays.severe),
+            },
+            tz: config.timezone,
+            layout: config.layout,
+            theme: config.theme,
+        }
+    }
+}
+
+/// When to point out that departures deviate from the timetable.
+struct DelayThresholds {
+    /// Smaller deviations aren't shown
+    shown: chrono::Duration,
+    severe: chrono::Duration,
+}
+
+impl DelayThresholds {
+    /// A label such as "+3" for departures far enough off schedule, along
+    /// with its colour.
+    fn label(
+        &self,
+        scheduled: DateTime<Utc>,
+        expected: DateTime<Utc>,
+        theme: &Theme,
+    ) -> Option<(String, Rgb)> {
+        let delay = expected - scheduled;
+        if delay.num_seconds().abs() < self.shown.num_seconds() {
+            return None;
+        }
+
+        let color = if delay >= self.severe {
+            theme.severe_delay
+        } else {
+            theme.delay
+        };
+
+        Some((format!("{:+}", delay.num_minutes()), color))
+    }
+}
+
+/// Tells how old the data on the board is, once it's old enough to matter.
+pub fn staleness_label(age: chrono::Duration) -> String {
+    if age < chrono::Duration::minutes(2) {
+        String::new()
+    } else if age < chrono::Duration::hours(1) {
+        format!("{} min old", age.num_minutes())
+    } else if age < chrono::Duration::days(1) {
+        format!("{} h old", age.num_hours())
+    } else {
+        format!("{} days old", age.num_days())
+    }
+}
+
+/// A departure on the board, with handles to its columns so that the
+/// countdown and colour can be updated without rebuilding the row.
+struct DepartureRow<P: PixelType> {
+    expected: DateTime<Utc>,
+    /// For showing the time of departures further away
+    tz: Tz,
+    /// Shown instead of the countdown for departures that won't leave
+    cancelled: Option<&'static str>,
+    /// Minutes
+    walk_time: u32,
+    theme: Theme,
+    /// ex: 19 Hagsätra
+    name: String,
+    time: Rc<TextGadget>,
+    /// The columns coloured by reachability
+    colored: Vec<Rc<TextGadget>>,
+    gadget: Rc<dyn Gadget<P>>,
+}
+
+impl<P: PixelType> DepartureRow<P> {
+    fn reachability(&self, now: DateTime<Utc>) -> Reachability {
+        Reachability::of(self.expected, self.walk_time, now)
+    }
+
+    /// Refreshes the countdown and colour, returning false once the
+    /// departure has left.
+    fn update(&self, now: DateTime<Utc>) -> bool {
+        let color = self.theme.reachability(self.reachability(now));
+        for column in &self.colored {
+            column.color(color);
+        }
+
+        match (countdown_label(self.expected, now, self.tz), self.cancelled) {
+            (Some(_), Some(status)) => {
+                self.time.text(status.to_string());
+                true
+            }
+            (Some(label), None) => {
+                self.time.text(label);
+                true
+            }
+            (None, _) => false,
+        }
+    }
+}
+
+/// Tells when to set off for the first departure that can still be caught.
+fn leave_label<P: PixelType>(rows: &[DepartureRow<P>], now: DateTime<Utc>) -> String {
+    let best = rows
+        .iter()
+        .filter(|row| row.cancelled.is_none())
+        .find(|row| row.reachability(now) != Reachability::Unreachable);
+    let row = match best {
+        Some(row) => row,
+        None => return String::new(),
+    };
+
+    let leave_in = row.expected - chrono::Duration::minutes(row.walk_time.into()) - now;
+    match row.reachability(now) {
+        Reachability::Run => format!("Run for {}", row.name),
+        _ if leave_in < chrono::Duration::minutes(1) => format!("Leave now for {}", row.name),
+        _ => format!("Leave in {} min for {}", leave_in.num_minutes(), row.name),
+    }
+}
+
+fn departure_rows<P: PixelType + 'static>(
+    board: &Board,
+    modes: &[TransportMode],
+    selected: Option<TransportMode>,
+    config: &RowConfig,
+    width: u32,
+) -> Vec<DepartureRow<P>> {
+    let layout = &config.layout;
+    let theme = &config.theme;
+    let height = layout.row_height;
+
+    // Tell the sites apart when there are several on the board
+    let site_width = if board
+        .departures
+        .iter()
+        .any(|x| x.stop_area_name != board.departures[0].stop_area_name)
+    {
+        layout.site_width
+    } else {
+        0
+    };
+
+    board
+        .departures
+        .iter()
+        .filter(|departure| config.filter.matches(departure))
+        .filter(|departure| mode_visible(departure.transport_mode, modes, selected))
+        .map(|departure| {
+            let marker = if departure.deviations.is_empty() { "" } else { "!" };
+            let cancelled = match departure.change {
+                _ if departure.cancelled => Some("Inställd"),
+                // Most likely cancelled, but we can't be sure
+                Some(Change::Vanished) => Some("Inställd?"),
+                _ => None,
+            };
+            let note = match departure.change {
+                Some(Change::Delayed(minutes)) => Some(format!("delayed by {}", minutes)),
+                Some(Change::Added) => Some("new departure".to_string()),
+                _ => None,
+            };
+            let delay = if cancelled.is_some() {
+                None
+            } else {
+                config.delays.label(departure.scheduled, departure.expected, theme)
+            };
+            let delay_width = if delay.is_some() {
+                layout.delay_width + layout.scheduled_width
+            } else {
+                0
+            };
+
+            let time = Rc::new(TextGadget::new(
+                String::new(),
+                layout.time_width,
+                height,
+                theme.departure,
+                // "Inställd" doesn't fit otherwise
+                if cancelled.is_some() { layout.small_font_size } else { layout.font_size },
+            ));
+
+            let line = Rc::new(TextGadget::new(
+                departure.line.clone(),
+                layout.line_width,
+                height,
+                theme.departure,
+                layout.font_size,
+            ));
+            let destination_width = width.saturating_sub(
+                16 + layout.line_width + site_width + delay_width + layout.time_width,
+            );
+            let destination = Rc::new(TextGadget::new(
+                departure.destination.clone(),
+                destination_width,
+                height,
+                theme.departure,
+                layout.font_size,
+            ));
+
+            // Rows with a note are made taller to fit it
+            let note_height = match note {
+                Some(_) => layout.small_font_size.ceil() as u32,
+                None => 0,
+            };
+            let mut row = HorizontalGadget::new(width, height + note_height);
+            row.children.push(Rc::new(TextGadget::new(
+                marker.to_string(),
+                16,
+                height,
+                theme.warning,
+                layout.font_size,
+            )));
+            row.children.push(line.clone());
+            match note {
+                // Squeeze the note in below the destination
+                Some(note) => {
+                    let column = ScrollGadget::new(destination_width, height + note_height);
+                    column.push(destination.clone());
+                    column.push(Rc::new(TextGadget::new(
+                        note,
+                        destination_width,
+                        note_height,
+                        theme.note,
+                        layout.small_font_size,
+                    )));
+                    row.children.push(Rc::new(column));
+                }
+                None => row.children.push(destination.clone()),
+            }
+            if let Some((label, color)) = delay {
+                row.children.push(Rc::new(TextGadget::new(
+                    label,
+                    layout.delay_width,
+                    height,
+                    color,
+                    layout.small_font_size,
+                )));
+
+                let scheduled = TextGadget::new(
+                    departure
+                        .scheduled
+                        .with_timezone(&config.tz)
+                        .format("%H:%M")
+                        .to_string(),
+                    layout.scheduled_width,
+                    height,
+                    color,
+                    layout.small_font_size,
+                );
+                scheduled.strikethrough(true);
+                row.children.push(Rc::new(scheduled));
+            }
+            if site_width > 0 {
+                row.children.push(Rc::new(TextGadget::new(
+                    departure.stop_area_name.clone(),
+                    site_width,
+                    height,
+                    theme.site,
+                    layout.small_font_size,
+                )));
+            }
+            row.children.push(time.clone());
+
+            DepartureRow {
+                expected: departure.expected,
+                tz: config.tz,
+                cancelled,
+                walk_time: config.walk_times.walk_time(departure),
+                theme: *theme,
+                name: format!("{} {}", departure.line, departure.destination),
+                time: time.clone(),
+                colored: vec![line, destination, time],
+                gadget: Rc::new(row),
+            }
+        })
+        .collect()
+}
+
+/// Stop level disruptions for the visible modes, most important first, broken
+/// into lines that fit the alert area.
+fn alert_lines(
+    board: &Board,
+    modes: &[TransportMode],
+    selected: Option<TransportMode>,
+    size: f32,
+    width: u32,
+) -> Vec<String> {
+    let mut deviations: Vec<_> = board
+        .alerts
+        .iter()
+        .filter(|x| match x.transport_mode {
+            Some(mode) => mode_visible(mode, modes, selected),
+            None => true,
+        })
+        .map(|x| &x.deviation)
+        .collect();
+    deviations.sort_by_key(|x| std::cmp::Reverse(x.importance));
+
+    departure::distinct_texts(deviations)
+        .into_iter()
+        .flat_map(|text| gadget::wrap_text(text, size, width))
+        .collect()
+}
+
+/// The board as laid out on the display: a header with the clock, the
+/// selected mode and how old the data is, a line telling when to leave, the
+/// alerts and then the departures.
+pub struct Screen<P: PixelType> {
+    /// Of the board, which is the height of the buffer as it's turned on its
+    /// side
+    width: u32,
+    height: u32,
+    modes: Vec<TransportMode>,
+    row_config: RowConfig,
+    outer_layout: ScrollGadget<P>,
+    header: Rc<dyn Gadget<P>>,
+    clock: Rc<TextGadget>,
+    mode_label: Rc<TextGadget>,
+    stale_badge: Rc<TextGadget>,
+    headline: Rc<TextGadget>,
+    alert: Rc<TextGadget>,
+    inner_layout: Rc<ScrollGadget<P>>,
+    /// Whether the headline is shown, which is only worth the space when
+    /// there's a walk to plan for
+    walking: bool,
+    rows: Vec<DepartureRow<P>>,
+    /// Whether the rows have changed since they were last laid out
+    rows_changed: bool,
+    alerts: Vec<String>,
+    scroll: isize,
+    selected_mode: Option<TransportMode>,
+}
+
+impl<P: PixelType + 'static> Screen<P> {
+    /// Lays out the board for `config`. `profile` is the name of the profile
+    /// in use, if the config has any.
+    pub fn new(config: &Config, profile: Option<&str>, width: u32, height: u32) -> Screen<P> {
+        let row_config = RowConfig::new(config);
+        let layout = row_config.layout;
+        let theme = row_config.theme;
+
+        let outer_layout = ScrollGadget::new(height, width);
+
+        let mut header = HorizontalGadget::new(width, layout.header_height);
+        let clock = Rc::new(TextGadget::new(
+            String::new(),
+            layout.clock_width,
+            layout.header_height,
+            theme.text,
+            layout.font_size,
+        ));
+        header.children.push(clock.clone());
+        let mode_label = Rc::new(TextGadget::new(
+            String::new(),
+            layout.mode_width,
+            if profile.is_none() {
+                layout.header_height
+            } else {
+                layout.header_height.saturating_sub(13)
+            },
+            theme.text,
+            layout.small_font_size,
+        ));
+        match profile {
+            // Squeeze the profile in below the mode
+            Some(profile) => {
+                let column = ScrollGadget::new(layout.mode_width, layout.header_height);
+                column.push(mode_label.clone());
+                column.push(Rc::new(TextGadget::new(
+                    profile.to_string(),
+                    layout.mode_width,
+                    13,
+                    theme.text,
+                    13.0,
+                )));
+                header.children.push(Rc::new(column));
+            }
+            None => header.children.push(mode_label.clone()),
+        }
+        let stale_badge = Rc::new(TextGadget::new(
+            String::new(),
+            width.saturating_sub(layout.clock_width + layout.mode_width),
+            layout.header_height,
+            theme.warning,
+            layout.small_font_size,
+        ));
+        header.children.push(stale_badge.clone());
+
+        let headline = Rc::new(TextGadget::new(
+            String::new(),
+            width,
+            layout.alert_height,
+            theme.text,
+            layout.small_font_size,
+        ));
+
+        let alert = Rc::new(TextGadget::new(
+            String::new(),
+            width,
+            layout.alert_height,
+            theme.alert,
+            layout.small_font_size,
+        ));
+
+        let inner_layout = Rc::new(ScrollGadget::new(width, height));
+
+        let screen = Screen {
+            width,
+            height,
+            modes: config.filters.visible_modes(),
+            row_config,
+            outer_layout,
+            header: Rc::new(header),
+            clock,
+            mode_label,
+            stale_badge,
+            headline,
+            alert,
+            inner_layout,
+            walking: false,
+            rows: vec![],
+            rows_changed: false,
+            alerts: vec![],
+            scroll: 0,
+            selected_mode: None,
+        };
+        screen.arrange();
+        screen
+    }
+
+    /// Stacks the header, the headline if it's shown, the alerts and the
+    /// departures.
+    fn arrange(&self) {
+        let mut children: Vec<Rc<dyn Gadget<P>>> = vec![self.header.clone()];
+        if self.walking {
+            children.push(self.headline.clone());
+        }
+        children.push(self.alert.clone());
+        children.push(self.inner_layout.clone());
+        self.outer_layout.set_children(children);
+    }
+
+    /// Replaces the departures and alerts with those on `board`.
+    pub fn show(&mut self, board: &Board) {
+        self.rows = departure_rows(
+            board,
+            &self.modes,
+            self.selected_mode,
+            &self.row_config,
+            self.width,
+        );
+        self.alerts = alert_lines(
+            board,
+            &self.modes,
+            self.selected_mode,
+            self.row_config.layout.small_font_size,
+            self.width,
+        );
+        self.rows_changed = true;
+
+        // The walk times can come from the stations as well as the config,
+        // so it's the departures that tell whether there's a walk
+        let walking = self.rows.iter().any(|row| row.walk_time > 0);
+        if walking != self.walking {
+            self.walking = walking;
+            self.arrange();
+        }
+    }
+
+    /// Cycles through showing all modes and then each mode on its own. The
+    /// board has to be shown again for it to take effect.
+    pub fn next_mode(&mut self) {
+        self.selected_mode = match self.selected_mode {
+            None => self.modes.first().copied(),
+            Some(mode) => self
+                .modes
+                .iter()
+                .skip_while(|&&x| x != mode)
+                .nth(1)
+                .copied(),
+        };
+    }
+
+    /// Scrolls the departures by `delta` rows, as far as there are any.
+    pub fn scroll(&mut self, delta: isize) {
+        self.scroll = (self.scroll + delta).clamp(0, self.max_scroll());
+        self.inner_layout.scroll(self.scroll as usize);
+    }
+
+    fn max_scroll(&self) -> isize {
+        let layout = &self.row_config.layout;
+        let headline_height = if self.walking { layout.alert_height } else { 0 };
+        let y_offset = layout.header_height + headline_height + layout.alert_height;
+
+        // As far as it goes with the last row still at the bottom, counting
+        // rows with notes as the taller ones they are
+        let mut room = self.height.saturating_sub(y_offset);
+        let mut fits = 0;
+        for row in self.rows.iter().rev() {
+            match room.checked_sub(row.gadget.height()) {
+                Some(left) => room = left,
+                None => break,
+            }
+            fits += 1;
+        }
+        (self.rows.len() - fits) as isize
+    }
+
+    /// Brings the clock, countdowns and alerts up to date with `now`, and
+    /// shows `stale` in the header.
+    pub fn update(&mut self, now: DateTime<Utc>, stale: String) {
+        let tz = self.row_config.tz;
+        self.clock.text(now.with_timezone(&tz).format("%H:%M:%S").to_string());
+        self.mode_label.text(
+            self.selected_mode
+                .map(|mode| mode.name().to_string())
+                .unwrap_or_default(),
+        );
+        self.stale_badge.text(stale);
+
+        // Page through the alerts, a few seconds per line
+        if self.alerts.is_empty() {
+            self.alert.text(String::new());
+        } else {
+            let page = (now.timestamp() / 3) as usize % self.alerts.len();
+            self.alert.text(self.alerts[page].clone());
+        }
+
+        // Count down locally between fetches and drop departures as they
+        // leave, rather than relying on the server's display time.
+        let row_count = self.rows.len();
+        self.rows.retain(|row| row.update(now));
+        if self.rows_changed || self.rows.len() != row_count {
+            self.inner_layout
+                .set_children(self.rows.iter().map(|row| row.gadget.clone()).collect());
+            self.rows_changed = false;
+
+            if self.scroll > self.max_scroll() {
+                self.scroll = self.max_scroll();
+                self.inner_layout.scroll(self.scroll as usize);
+            }
+        }
+        if self.walking {
+            self.headline.text(leave_label(&self.rows, now));
+        }
+    }
+
+    /// Draws whatever has changed into `buffer`, returning false if nothing
+    /// had.
+    pub fn render(&self, buffer: &mut Buffer<P>) -> bool {
+        if !self.outer_layout.dirty() {
+            return false;
+        }
+
+        let rect = RenderRect {
+            x: 0,
+            y: 0,
+            width: self.width,
+            height: self.height,
+        };
+        self.outer_layout.render(rect, buffer);
+        true
+    }
+}
@@ -0,0 +1,202 @@
+use std::fmt;
+
+use crate::{
+    source::{http_get, FetchError},
+    trafiklab::{self, nearby},
+    Result,
+};
+
+/// A stop found by name or position, to be shown with its site id so that it
+/// can be passed as --station-id.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StopMatch {
+    pub name: String,
+    pub site_id: u32,
+    /// ex: tunnelbana, buss
+    pub modes: Vec<String>,
+    /// Meters away, when searching by position
+    pub distance: Option<u32>,
+}
+
+impl fmt::Display for StopMatch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:>8}  {:<36} {}", self.site_id, self.name, self.modes.join(", "))?;
+        if let Some(distance) = self.distance {
+            write!(f, " ({} m)", distance)?;
+        }
+        Ok(())
+    }
+}
+
+/// A position, written as latitude,longitude, ex: 59.3203,18.0719.
+#[derive(Debug, Clone, Copy)]
+pub struct Coordinates {
+    pub lat: f64,
+    pub lon: f64,
+}
+
+impl std::str::FromStr for Coordinates {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let invalid = || format!("expected <latitude>,<longitude>: {}", s);
+        let pos = s.find(',').ok_or_else(invalid)?;
+
+        Ok(Coordinates {
+            lat: s[..pos].trim().parse().map_err(|_| invalid())?,
+            lon: s[pos + 1..].trim().parse().map_err(|_| invalid())?,
+        })
+    }
+}
+
+/// Looks up stations by name with SL's typeahead API, which needs a key for
+/// SL Platsuppslag from Trafiklab.
+pub fn by_name(api_key: &str, query: &str) -> Result<Vec<StopMatch>> {
+    let url = format!(
+        "https://api.sl.se/api2/typeahead.json?key={}&searchstring={}&stationsonly=true&maxresults=20",
+        api_key,
+        encode(query),
+    );
+
+    Ok(parse_typeahead(&http_get(&url)?)?)
+}
+
+/// Looks up the stops within `radius` meters of `position` with SL's
+/// nearbystopsv2 API, which needs a key for SL Närliggande hållplatser 2
+/// from Trafiklab.
+pub fn nearby(api_key: &str, position: Coordinates, radius: u32) -> Result<Vec<StopMatch>> {
+    let url = format!(
+        "https://api.sl.se/api2/nearbystopsv2.json?key={}&originCoordLat={}&originCoordLong={}&r={}&maxNo=20",
+        api_key, position.lat, position.lon, radius,
+    );
+
+    Ok(parse_nearby(&http_get(&url)?)?)
+}
+
+pub fn parse_typeahead(body: &str) -> std::result::Result<Vec<StopMatch>, FetchError> {
+    let response: trafiklab::TypeaheadResponse = serde_json::from_str(body)?;
+    let sites = match response {
+        trafiklab::TypeaheadResponse {
+            status_code: 0,
+            response_data: Some(sites),
+            ..
+        } => sites,
+        trafiklab::TypeaheadResponse { status_code, message, .. } => {
+            return Err(FetchError::Api {
+                status_code,
+                message: message.unwrap_or_default(),
+            });
+        }
+    };
+
+    Ok(sites
+        .into_iter()
+        .filter_map(|site| {
+            Some(StopMatch {
+                site_id: site.site_id.parse().ok()?,
+                name: site.name,
+                modes: site
+                    .products
+                    .iter()
+                    .flat_map(|products| products.split(','))
+                    .map(|mode| mode.trim().to_lowercase())
+                    .filter(|mode| !mode.is_empty())
+                    .collect(),
+                distance: None,
+            })
+        })
+        .collect())
+}
+
+pub fn parse_nearby(body: &str) -> std::result::Result<Vec<StopMatch>, FetchError> {
+    let response: nearby::NearbyStopsResponse = serde_json::from_str(body)?;
+    if let Some(code) = response.error_code {
+        return Err(FetchError::Api {
+            status_code: 0,
+            message: format!("{}: {}", code, response.error_text.unwrap_or_default()),
+        });
+    }
+
+    Ok(response
+        .stop_location_or_coord_location
+        .into_iter()
+        .filter_map(|location| {
+            let stop = location.stop_location?;
+
+            // There's a product per line, so the same mode turns up many
+            // times over
+            let mut modes: Vec<String> = vec![];
+            for mode in stop.product_at_stop.iter().filter_map(|x| x.cat_out_l.as_ref()) {
+                let mode = mode.to_lowercase();
+                if !modes.contains(&mode) {
+                    modes.push(mode);
+                }
+            }
+
+            Some(StopMatch {
+                site_id: stop.ext_id.strip_prefix("3001").unwrap_or(&stop.ext_id).parse().ok()?,
+                name: stop.name,
+                modes,
+                distance: stop.dist,
+            })
+        })
+        .collect())
+}
+
+/// Percent-encodes `value` for use in a query string.
+fn encode(value: &str) -> String {
+    let mut encoded = String::new();
+    for byte in value.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                encoded.push(byte as char)
+            }
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_typeahead_response() {
+        let body = std::fs::read_to_string("./test/data/typeahead.json").unwrap();
+        let stops = parse_typeahead(&body).unwrap();
+
+        assert_eq!(stops.len(), 3);
+        assert_eq!(stops[0].name, "Slussen (Stockholm)");
+        assert_eq!(stops[0].site_id, 9192);
+        assert_eq!(stops[0].modes, vec!["metro", "bus", "ship"]);
+        assert!(stops[1].modes.is_empty());
+    }
+
+    #[test]
+    fn parses_nearby_response() {
+        let body = std::fs::read_to_string("./test/data/nearby.json").unwrap();
+        let stops = parse_nearby(&body).unwrap();
+
+        // The address entry is left out
+        assert_eq!(stops.len(), 2);
+        assert_eq!(stops[0].site_id, 9192);
+        assert_eq!(stops[0].modes, vec!["tunnelbana", "buss"]);
+        assert_eq!(stops[0].distance, Some(134));
+        assert_eq!(stops[1].site_id, 1321);
+    }
+
+    #[test]
+    fn reports_rejected_key() {
+        let body = r#"{"StatusCode":1002,"Message":"Key is invalid","ExecutionTime":0}"#;
+        match parse_typeahead(body) {
+            Err(FetchError::Api { status_code: 1002, .. }) => {}
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn encodes_query() {
+        assert_eq!(encode("Tekniska högskolan"), "Tekniska%20h%C3%B6gskolan");
+    }
+}
@@ -0,0 +1,30 @@
+use std::path::PathBuf;
+
+use chrono::Utc;
+use chrono_tz::Tz;
+
+use crate::{
+    departure::Board,
+    source::{sl_v4, DepartureSource},
+    Result,
+};
+
+/// Departures from a realtimedeparturesV4 response saved to disk, for
+/// development without an API key or network.
+pub struct FixtureSource {
+    path: PathBuf,
+    tz: Tz,
+}
+
+impl FixtureSource {
+    pub fn new(path: PathBuf, tz: Tz) -> FixtureSource {
+        FixtureSource { path, tz }
+    }
+}
+
+impl DepartureSource for FixtureSource {
+    fn fetch(&mut self) -> Result<Board> {
+        let body = std::fs::read_to_string(&self.path)?;
+        Ok(sl_v4::parse(&body, self.tz, Utc::now())?)
+    }
+}
@@ -0,0 +1,343 @@
+use std::collections::HashSet;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use chrono::{prelude::*, Duration};
+use chrono_tz::Tz;
+use prost::Message;
+
+use crate::{
+    departure::{Alert, Board, Departure, Deviation},
+    gtfs::{self, realtime, Timetable},
+    source::{read_feed, DepartureSource, FetchError},
+    time, Result,
+};
+
+/// How far ahead trips missing from the feed are shown as scheduled.
+const SCHEDULED_WINDOW: i64 = 60;
+
+/// Departures from a GTFS-Realtime TripUpdates feed, joined with the static
+/// GTFS feed for names, lines and the timetable.
+pub struct GtfsRealtimeSource {
+    /// URL or path of the TripUpdates feed
+    feed: String,
+    static_path: PathBuf,
+    stop_ids: Vec<String>,
+    /// Loaded on the first fetch unless it's been loaded already, since it
+    /// can take a while
+    timetable: Option<Arc<Timetable>>,
+    /// The timezone of the timetable
+    tz: Tz,
+}
+
+impl GtfsRealtimeSource {
+    pub fn new(
+        feed: String,
+        static_path: PathBuf,
+        stop_ids: Vec<String>,
+        timetable: Option<Arc<Timetable>>,
+        tz: Tz,
+    ) -> GtfsRealtimeSource {
+        GtfsRealtimeSource {
+            feed,
+            static_path,
+            stop_ids,
+            timetable,
+            tz,
+        }
+    }
+}
+
+impl DepartureSource for GtfsRealtimeSource {
+    fn fetch(&mut self) -> Result<Board> {
+        let timetable = match self.timetable.take() {
+            Some(timetable) => timetable,
+            None => Arc::new(Timetable::load(&self.static_path, &self.stop_ids)?),
+        };
+        let timetable = self.timetable.insert(timetable);
+
+        let body = read_feed(&self.feed)?;
+        let feed = realtime::FeedMessage::decode(&body[..]).map_err(FetchError::from)?;
+
+        Ok(board(&feed, timetable, Utc::now(), self.tz))
+    }
+}
+
+/// Maps the trip updates for trips calling at our stops onto departures.
+/// Trips the feed doesn't mention are taken to run on time.
+pub fn board(
+    feed: &realtime::FeedMessage,
+    timetable: &Timetable,
+    now: DateTime<Utc>,
+    tz: Tz,
+) -> Board {
+    let mut departures = vec![];
+    let mut alerts = vec![];
+    let mut updated_trips = HashSet::new();
+
+    for entity in &feed.entity {
+        if entity.is_deleted == Some(true) {
+            continue;
+        }
+
+        if let Some(update) = &entity.trip_update {
+            if let Some(trip_id) = &update.trip.trip_id {
+                updated_trips.insert(trip_id.as_str());
+            }
+            departures.extend(trip_departures(update, timetable, time::local_date(tz, now), tz));
+        }
+
+        if let Some(alert) = &entity.alert {
+            let informs_us = alert.informed_entity.iter().any(|selector| {
+                selector
+                    .stop_id
+                    .as_ref()
+                    .is_some_and(|id| timetable.stops.contains_key(id))
+                    || selector
+                        .route_id
+                        .as_ref()
+                        .is_some_and(|id| timetable.routes.contains_key(id))
+            });
+            let text = alert.header_text.as_ref().and_then(|x| x.text());
+            if let (true, Some(text)) = (informs_us, text) {
+                alerts.push(Alert {
+                    transport_mode: None,
+                    deviation: Deviation {
+                        text: text.to_string(),
+                        consequence: alert
+                            .description_text
+                            .as_ref()
+                            .and_then(|x| x.text())
+                            .map(str::to_string),
+                        importance: alert.severity_level.unwrap_or(1).max(0) as u32,
+                    },
+                });
+            }
+        }
+    }
+
+    // Feeds commonly leave out trips that run to schedule
+    let scheduled = timetable.scheduled_board(now, Duration::minutes(SCHEDULED_WINDOW), tz);
+    departures.extend(
+        scheduled
+            .departures
+            .into_iter()
+            .filter(|departure| !updated_trips.contains(departure.journey_id.as_str())),
+    );
+
+    let data_age = feed
+        .header
+        .timestamp
+        .and_then(|timestamp| Utc.timestamp_opt(timestamp as i64, 0).single())
+        .map(|generated| (now - generated).num_seconds().max(0) as u32)
+        .unwrap_or(0);
+
+    Board::new(departures, alerts, data_age)
+}
+
+fn trip_departures(
+    update: &realtime::TripUpdate,
+    timetable: &Timetable,
+    today: NaiveDate,
+    tz: Tz,
+) -> Vec<Departure> {
+    let trip_id = match &update.trip.trip_id {
+        Some(trip_id) => trip_id,
+        None => return vec![],
+    };
+    let calls = match timetable.stop_times.get(trip_id) {
+        Some(calls) => calls,
+        None => return vec![],
+    };
+
+    let service_date = update
+        .trip
+        .start_date
+        .as_ref()
+        .and_then(|date| NaiveDate::parse_from_str(date, "%Y%m%d").ok())
+        .unwrap_or(today);
+    let trip_cancelled = update.trip.schedule_relationship
+        == Some(realtime::TripScheduleRelationship::Canceled as i32);
+
+    calls
+        .iter()
+        .filter_map(|call| {
+            let mut departure = timetable.departure(trip_id, call, service_date, tz)?;
+            let (expected, skipped) = expected_departure(update, call, departure.scheduled);
+            departure.expected = expected;
+            departure.cancelled = trip_cancelled || skipped;
+            Some(departure)
+        })
+        .collect()
+}
+
+/// Works out when the trip is expected at `call`, and whether it skips the
+/// stop. Delays carry over from earlier stops when there's no update for
+/// this one, as the GTFS-Realtime spec prescribes.
+fn expected_departure(
+    update: &realtime::TripUpdate,
+    call: &gtfs::StopTime,
+    scheduled: DateTime<Utc>,
+) -> (DateTime<Utc>, bool) {
+    let this_stop = |x: &&realtime::StopTimeUpdate| {
+        x.stop_sequence == Some(call.stop_sequence) || x.stop_id.as_ref() == Some(&call.stop_id)
+    };
+
+    if let Some(stop_update) = update.stop_time_update.iter().find(this_stop) {
+        let skipped = stop_update.schedule_relationship
+            == Some(realtime::StopScheduleRelationship::Skipped as i32);
+        let event = stop_update.departure.as_ref().or(stop_update.arrival.as_ref());
+        // Times out of range are ignored rather than trusted
+        let time = event
+            .and_then(|event| event.time)
+            .and_then(|time| Utc.timestamp_opt(time, 0).single());
+        let delay = event
+            .and_then(|event| event.delay)
+            .map(|delay| scheduled + Duration::seconds(delay.into()));
+        return (time.or(delay).unwrap_or(scheduled), skipped);
+    }
+
+    let earlier_delay = update
+        .stop_time_update
+        .iter()
+        .filter(|x| x.stop_sequence.is_some_and(|sequence| sequence < call.stop_sequence))
+        .max_by_key(|x| x.stop_sequence)
+        .and_then(|x| x.departure.as_ref().or(x.arrival.as_ref()))
+        .and_then(|event| event.delay);
+
+    match earlier_delay.or(update.delay) {
+        Some(delay) => (scheduled + Duration::seconds(delay.into()), false),
+        None => (scheduled, false),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn timetable() -> Timetable {
+        Timetable::load("./test/data/gtfs.zip", &["9001".to_string()]).unwrap()
+    }
+
+    fn call(stop_id: &str, stop_sequence: u32) -> gtfs::StopTime {
+        gtfs::StopTime {
+            stop_id: stop_id.to_string(),
+            stop_sequence,
+            departure_time: 0,
+            stop_headsign: None,
+        }
+    }
+
+    fn stop_update(stop_sequence: u32, event: realtime::StopTimeEvent) -> realtime::StopTimeUpdate {
+        realtime::StopTimeUpdate {
+            stop_sequence: Some(stop_sequence),
+            departure: Some(event),
+            ..Default::default()
+        }
+    }
+
+    fn trip_update(trip_id: &str) -> realtime::TripUpdate {
+        realtime::TripUpdate {
+            trip: realtime::TripDescriptor {
+                trip_id: Some(trip_id.to_string()),
+                start_date: Some("20240314".to_string()),
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn delays_carry_over_to_later_stops() {
+        let scheduled = Utc.ymd(2024, 3, 14).and_hms(22, 50, 0);
+        let mut update = trip_update("evening");
+        update.delay = Some(30);
+        update.stop_time_update = vec![
+            stop_update(
+                2,
+                realtime::StopTimeEvent {
+                    delay: Some(120),
+                    ..Default::default()
+                },
+            ),
+            stop_update(
+                4,
+                realtime::StopTimeEvent {
+                    time: Some(scheduled.timestamp() + 300),
+                    delay: Some(60),
+                    ..Default::default()
+                },
+            ),
+            realtime::StopTimeUpdate {
+                stop_id: Some("9012".to_string()),
+                schedule_relationship: Some(realtime::StopScheduleRelationship::Skipped as i32),
+                ..Default::default()
+            },
+        ];
+
+        // The time wins over the delay, and earlier delays over the trip's
+        let expected = |call| expected_departure(&update, &call, scheduled);
+        assert_eq!(expected(call("9011", 4)), (scheduled + Duration::minutes(5), false));
+        assert_eq!(expected(call("9011", 3)), (scheduled + Duration::minutes(2), false));
+        assert_eq!(expected(call("9011", 1)), (scheduled + Duration::seconds(30), false));
+        assert!(expected(call("9012", 5)).1);
+
+        // Out of range times fall back on the delay
+        update.stop_time_update[1].departure.as_mut().unwrap().time = Some(i64::MAX);
+        let expected = expected_departure(&update, &call("9011", 4), scheduled);
+        assert_eq!(expected, (scheduled + Duration::minutes(1), false));
+    }
+
+    #[test]
+    fn cancelled_trips_are_kept_as_cancelled() {
+        let timetable = timetable();
+        let tz = chrono_tz::Europe::Stockholm;
+        let today = NaiveDate::from_ymd(2024, 3, 14);
+
+        let mut update = trip_update("evening");
+        update.trip.schedule_relationship =
+            Some(realtime::TripScheduleRelationship::Canceled as i32);
+        let departures = trip_departures(&update, &timetable, today, tz);
+        assert_eq!(departures.len(), 1);
+        assert!(departures[0].cancelled);
+        assert_eq!(departures[0].line, "19");
+        assert_eq!(departures[0].destination, "Hagsätra");
+
+        assert!(trip_departures(&trip_update("bus"), &timetable, today, tz).is_empty());
+    }
+
+    #[test]
+    fn fills_in_trips_without_updates() {
+        let timetable = timetable();
+        let tz = chrono_tz::Europe::Stockholm;
+        // Just after midnight, with the evening trip running late
+        let now = Utc.ymd(2024, 3, 14).and_hms(23, 15, 0);
+
+        let mut update = trip_update("evening");
+        update.delay = Some(180);
+        let feed = realtime::FeedMessage {
+            header: realtime::FeedHeader {
+                gtfs_realtime_version: "2.0".to_string(),
+                timestamp: Some(i64::MAX as u64),
+            },
+            entity: vec![realtime::FeedEntity {
+                id: "1".to_string(),
+                trip_update: Some(update),
+                ..Default::default()
+            }],
+        };
+
+        let board = board(&feed, &timetable, now, tz);
+        let departures: Vec<_> = board
+            .departures
+            .iter()
+            .map(|x| (x.journey_id.as_str(), x.expected - x.scheduled))
+            .collect();
+        assert_eq!(
+            departures,
+            vec![("evening", Duration::minutes(3)), ("late", Duration::zero())]
+        );
+        // A timestamp out of range says nothing about the age
+        assert_eq!(board.data_age, 0);
+    }
+}
@@ -0,0 +1,56 @@
+use chrono_tz::Tz;
+
+use crate::{
+    departure::Board,
+    source::{create, DepartureSource, Site},
+    Result,
+};
+
+/// Combines the departures from several sites into one board, noting how
+/// long it takes to walk to each of them.
+pub struct MergedSource {
+    sites: Vec<(Box<dyn DepartureSource>, u32)>,
+}
+
+impl MergedSource {
+    pub fn new(sites: &[Site], tz: Tz) -> MergedSource {
+        MergedSource {
+            sites: sites
+                .iter()
+                .map(|site| (create(&site.source, tz, None), site.walk_time))
+                .collect(),
+        }
+    }
+}
+
+impl DepartureSource for MergedSource {
+    fn fetch(&mut self) -> Result<Board> {
+        // A site failing fails the whole board, rather than quietly showing
+        // only some of the departures
+        let mut boards = vec![];
+        for (source, walk_time) in &mut self.sites {
+            boards.push((source.fetch()?, *walk_time));
+        }
+
+        Ok(merge(boards))
+    }
+}
+
+/// Puts the departures from several boards on one, along with the minutes
+/// it takes to walk to each board's site.
+pub fn merge(boards: Vec<(Board, u32)>) -> Board {
+    let mut departures = vec![];
+    let mut alerts = vec![];
+    let mut data_age = 0;
+
+    for (board, walk_time) in boards {
+        departures.extend(board.departures.into_iter().map(|mut departure| {
+            departure.walk_time = walk_time;
+            departure
+        }));
+        alerts.extend(board.alerts);
+        data_age = data_age.max(board.data_age);
+    }
+
+    Board::new(departures, alerts, data_age)
+}
@@ -0,0 +1,235 @@
+use std::collections::HashMap;
+use std::io::Read;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use chrono_tz::Tz;
+
+use crate::{departure::Board, gtfs::Timetable, Error, Result};
+
+pub mod fixture;
+pub mod gtfs_rt;
+pub mod merged;
+pub mod record;
+pub mod replay;
+pub mod siri;
+pub mod sl_transport;
+pub mod sl_v4;
+
+/// Somewhere to fetch departures from. Sources are moved onto the fetcher
+/// thread, hence `Send`.
+pub trait DepartureSource: Send {
+    fn fetch(&mut self) -> Result<Board>;
+}
+
+/// Which source to use, and what it needs to know.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SourceConfig {
+    /// SL's realtimedeparturesV4 API
+    SlV4 { api_key: String, site_id: u32 },
+    /// SL's key-less Transport API
+    SlTransport { site_id: u32 },
+    /// A GTFS-Realtime TripUpdates feed, from a URL or a file, along with
+    /// the static GTFS zip it refers to
+    GtfsRealtime {
+        feed: String,
+        static_path: PathBuf,
+        stop_ids: Vec<String>,
+    },
+    /// A SIRI StopMonitoring service, or a response saved to a file
+    Siri { feed: String },
+    /// A recorded realtimedeparturesV4 response
+    Fixture { path: PathBuf },
+    /// Responses recorded over time, along with the minutes it takes to
+    /// walk to each site
+    Replay {
+        recording: replay::Recording,
+        walk_times: HashMap<u32, u32>,
+    },
+    /// Several sites shown on the same board
+    Merged { sites: Vec<Site> },
+}
+
+/// One of the sites on a merged board.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Site {
+    pub source: SourceConfig,
+    /// Minutes it takes to walk to the site
+    pub walk_time: u32,
+}
+
+impl SourceConfig {
+    /// Whether the source talks to a live backend, as opposed to replaying
+    /// recorded data.
+    pub fn is_live(&self) -> bool {
+        match self {
+            SourceConfig::SlV4 { .. } => true,
+            SourceConfig::SlTransport { .. } => true,
+            SourceConfig::GtfsRealtime { .. } => true,
+            SourceConfig::Siri { .. } => true,
+            SourceConfig::Fixture { .. } => false,
+            SourceConfig::Replay { .. } => false,
+            SourceConfig::Merged { sites } => sites.iter().any(|site| site.source.is_live()),
+        }
+    }
+
+    /// How many requests a single fetch makes to the backend, which is what
+    /// API quotas are counted in.
+    pub fn api_calls(&self) -> u32 {
+        match self {
+            SourceConfig::Merged { sites } => sites.iter().map(|site| site.source.api_calls()).sum(),
+            config if config.is_live() => 1,
+            _ => 0,
+        }
+    }
+}
+
+/// Creates the source for `config`. Times without an offset are read in
+/// `tz`. Sources that need the static GTFS feed use `timetable` if it's
+/// already been loaded.
+pub fn create(
+    config: &SourceConfig,
+    tz: Tz,
+    timetable: Option<Arc<Timetable>>,
+) -> Box<dyn DepartureSource> {
+    match config {
+        SourceConfig::SlV4 { api_key, site_id } => {
+            Box::new(sl_v4::SlV4Source::new(api_key.clone(), *site_id, tz))
+        }
+        SourceConfig::SlTransport { site_id } => {
+            Box::new(sl_transport::SlTransportSource::new(*site_id, tz))
+        }
+        SourceConfig::GtfsRealtime {
+            feed,
+            static_path,
+            stop_ids,
+        } => Box::new(gtfs_rt::GtfsRealtimeSource::new(
+            feed.clone(),
+            static_path.clone(),
+            stop_ids.clone(),
+            timetable,
+            tz,
+        )),
+        SourceConfig::Siri { feed } => Box::new(siri::SiriSource::new(feed.clone())),
+        SourceConfig::Fixture { path } => Box::new(fixture::FixtureSource::new(path.clone(), tz)),
+        SourceConfig::Replay {
+            recording,
+            walk_times,
+        } => Box::new(replay::ReplaySource::new(recording.clone(), walk_times.clone(), tz)),
+        SourceConfig::Merged { sites } => Box::new(merged::MergedSource::new(sites, tz)),
+    }
+}
+
+#[derive(Debug)]
+pub enum FetchError {
+    /// No response was received, e.g. because DNS or the connection failed
+    Network(ureq::Error),
+    /// The server responded with something other than 200 OK
+    Http { status: u16, body: String },
+    /// The backend rejected the request, e.g. because the key is invalid or
+    /// the quota is exceeded
+    Api { status_code: u32, message: String },
+    /// The response wasn't in the format we expected
+    Decode(Error),
+}
+
+impl FetchError {
+    /// Whether the request was turned down because we've made too many.
+    pub fn is_quota_exceeded(&self) -> bool {
+        match self {
+            FetchError::Http { status, .. } => *status == 429,
+            // Trafiklab's codes for too many requests per minute and per
+            // month
+            FetchError::Api { status_code, .. } => *status_code == 1006 || *status_code == 1007,
+            _ => false,
+        }
+    }
+}
+
+impl std::fmt::Display for FetchError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            FetchError::Network(e) => write!(f, "network error: {}", e),
+            FetchError::Http { status, body } => write!(f, "http error {}: {}", status, body),
+            FetchError::Api { status_code, message } => {
+                write!(f, "api error {}: {}", status_code, message)
+            }
+            FetchError::Decode(e) => write!(f, "invalid response: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for FetchError {}
+
+impl From<serde_json::Error> for FetchError {
+    fn from(e: serde_json::Error) -> Self {
+        FetchError::Decode(e.into())
+    }
+}
+
+impl From<prost::DecodeError> for FetchError {
+    fn from(e: prost::DecodeError) -> Self {
+        FetchError::Decode(e.into())
+    }
+}
+
+impl From<roxmltree::Error> for FetchError {
+    fn from(e: roxmltree::Error) -> Self {
+        FetchError::Decode(e.into())
+    }
+}
+
+impl From<chrono::ParseError> for FetchError {
+    fn from(e: chrono::ParseError) -> Self {
+        FetchError::Decode(e.into())
+    }
+}
+
+/// Reads a feed from `location`, which is either an http(s) URL or the path
+/// of a file.
+pub fn read_feed(location: &str) -> Result<Vec<u8>> {
+    if location.starts_with("http://") || location.starts_with("https://") {
+        Ok(http_get_bytes(location)?)
+    } else {
+        Ok(std::fs::read(location)?)
+    }
+}
+
+/// Fetches `url` and returns the body, mapping failures onto `FetchError`.
+pub fn http_get(url: &str) -> std::result::Result<String, FetchError> {
+    http_request(url)?
+        .into_string()
+        .map_err(|e| FetchError::Network(ureq::Error::Io(e)))
+}
+
+/// Like `http_get`, for binary responses.
+pub fn http_get_bytes(url: &str) -> std::result::Result<Vec<u8>, FetchError> {
+    let mut body = vec![];
+    http_request(url)?
+        .into_reader()
+        .read_to_end(&mut body)
+        .map_err(|e| FetchError::Network(ureq::Error::Io(e)))?;
+
+    Ok(body)
+}
+
+fn http_request(url: &str) -> std::result::Result<ureq::Response, FetchError> {
+    let response = ureq::get(url)
+        .timeout(std::time::Duration::from_secs(30))
+        .call();
+    if response.synthetic() {
+        // ureq reports transport failures as made up responses, with the
+        // underlying error attached
+        let e = response
+            .into_synthetic_error()
+            .expect("synthetic response without an error");
+        return Err(FetchError::Network(e));
+    }
+    if !response.ok() {
+        let status = response.status();
+        let body = response.into_string().unwrap_or_default();
+        return Err(FetchError::Http { status, body });
+    }
+
+    Ok(response)
+}
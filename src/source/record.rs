@@ -0,0 +1,153 @@
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Utc};
+use chrono_tz::Tz;
+
+use crate::{
+    source::{http_get, read_feed, replay, sl_transport, sl_v4, SourceConfig},
+    Result,
+};
+
+/// A response as the backend sent it, before it's been parsed.
+struct RawResponse {
+    /// The source it came from, as named in the config
+    source: &'static str,
+    site_id: Option<u32>,
+    extension: &'static str,
+    body: String,
+}
+
+/// Fetches the responses a board for `config` is made from.
+fn fetch_raw(config: &SourceConfig, responses: &mut Vec<RawResponse>) -> Result<()> {
+    match config {
+        SourceConfig::SlV4 { api_key, site_id } => responses.push(RawResponse {
+            source: "sl",
+            site_id: Some(*site_id),
+            extension: "json",
+            body: http_get(&sl_v4::url(api_key, *site_id))?,
+        }),
+        SourceConfig::SlTransport { site_id } => responses.push(RawResponse {
+            source: "sl-transport",
+            site_id: Some(*site_id),
+            extension: "json",
+            body: http_get(&sl_transport::url(*site_id))?,
+        }),
+        SourceConfig::Siri { feed } => responses.push(RawResponse {
+            source: "siri",
+            site_id: None,
+            extension: "xml",
+            body: String::from_utf8(read_feed(feed)?)?,
+        }),
+        SourceConfig::Merged { sites } => {
+            for site in sites {
+                fetch_raw(&site.source, responses)?;
+            }
+        }
+        SourceConfig::GtfsRealtime { .. }
+        | SourceConfig::Fixture { .. }
+        | SourceConfig::Replay { .. } => {
+            return Err("only the sl, sl-transport and siri sources can be recorded".into())
+        }
+    }
+
+    Ok(())
+}
+
+/// Saves the responses from every fetch to a directory, as a recording
+/// that can be replayed.
+pub struct Recorder {
+    dir: PathBuf,
+    tz: Tz,
+    /// Blanked out of the responses, in case the backend repeats it, so that
+    /// recordings can be shared
+    api_key: Option<String>,
+}
+
+impl Recorder {
+    pub fn new<P: AsRef<Path>>(dir: P, tz: Tz, api_key: Option<String>) -> Result<Recorder> {
+        std::fs::create_dir_all(&dir)?;
+        Ok(Recorder {
+            dir: dir.as_ref().to_path_buf(),
+            tz,
+            api_key: api_key.filter(|api_key| !api_key.is_empty()),
+        })
+    }
+
+    /// Fetches from `source` and saves the responses, returning where they
+    /// went. Nothing is saved unless every response can be replayed.
+    pub fn record(
+        &self,
+        source: &SourceConfig,
+        fetched_at: DateTime<Utc>,
+    ) -> Result<Vec<PathBuf>> {
+        let mut responses = vec![];
+        fetch_raw(source, &mut responses)?;
+        for response in &responses {
+            replay::parse(response.source, &response.body, self.tz, fetched_at)?;
+        }
+
+        let mut paths = vec![];
+        for response in responses {
+            let body = match &self.api_key {
+                Some(api_key) => response.body.replace(api_key.as_str(), "REDACTED"),
+                None => response.body,
+            };
+
+            // Replays skip hidden files, so they never see a half written
+            // response
+            let name = replay::file_name(
+                fetched_at,
+                response.source,
+                response.site_id,
+                response.extension,
+            );
+            let path = self.dir.join(&name);
+            let tmp_path = self.dir.join(format!(".{}", name));
+            std::fs::write(&tmp_path, body)?;
+            std::fs::rename(&tmp_path, &path)?;
+            paths.push(path);
+        }
+
+        Ok(paths)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::source::{create, replay::Recording};
+
+    #[test]
+    fn records_what_replays_read() {
+        let dir = std::env::temp_dir();
+        let dir = dir.join(format!("triportreat-record-{}", std::process::id()));
+        let tz = chrono_tz::Europe::Stockholm;
+        let fetched_at = "2024-03-12T07:00:00Z".parse().unwrap();
+        let source = SourceConfig::Siri {
+            feed: "./test/data/siri.xml".to_string(),
+        };
+
+        // Standing in for a key, as the feed doesn't need one
+        let recorder = Recorder::new(&dir, tz, Some("ENT".to_string())).unwrap();
+        let paths = recorder.record(&source, fetched_at).unwrap();
+        assert_eq!(paths, vec![dir.join("20240312T070000Z-siri.xml")]);
+        let body = std::fs::read_to_string(&paths[0]).unwrap();
+        assert!(body.contains("<ProducerRef>REDACTED</ProducerRef>"));
+
+        let recording = Recording::open(&dir).unwrap();
+        assert_eq!(recording.frames[0].fetched_at, fetched_at);
+        let board = create(
+            &SourceConfig::Replay {
+                recording,
+                walk_times: Default::default(),
+            },
+            tz,
+            None,
+        )
+        .fetch()
+        .unwrap();
+        assert!(!board.departures.is_empty());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
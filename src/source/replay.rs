@@ -0,0 +1,221 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, TimeZone, Utc};
+use chrono_tz::Tz;
+
+use crate::{
+    departure::Board,
+    source::{merged, siri, sl_transport, sl_v4, DepartureSource},
+    Result,
+};
+
+/// How fetch times are written in file names.
+const TIMESTAMP_FORMAT: &str = "%Y%m%dT%H%M%SZ";
+
+/// Responses recorded over time, kept in a directory with a file per
+/// response. Files are named by when the response was fetched, the source
+/// it came from and the site, if any, ex: 20201226T231047Z-sl-1011.json.
+/// Responses fetched together from several sites share the timestamp.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Recording {
+    /// Ordered by when they were fetched
+    pub frames: Vec<Frame>,
+}
+
+/// The responses from a single fetch.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Frame {
+    pub fetched_at: DateTime<Utc>,
+    pub responses: Vec<Response>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Response {
+    /// sl, sl-transport or siri
+    pub source: String,
+    pub site_id: Option<u32>,
+    pub path: PathBuf,
+}
+
+impl Response {
+    /// Makes sense of a file name such as 20201226T231047Z-sl-1011.json,
+    /// returning when the response was fetched along with the response.
+    fn parse(path: PathBuf) -> Option<(DateTime<Utc>, Response)> {
+        let name = path.file_name()?.to_str()?;
+        let stem = name.split('.').next()?;
+        let (timestamp, rest) = stem.split_once('-')?;
+        let fetched_at = Utc.datetime_from_str(timestamp, TIMESTAMP_FORMAT).ok()?;
+
+        // The site id comes last, as source names may contain dashes
+        let site = rest
+            .rsplit_once('-')
+            .and_then(|(source, site_id)| Some((source, site_id.parse().ok()?)));
+        let (source, site_id) = match site {
+            Some((source, site_id)) => (source, Some(site_id)),
+            None => (rest, None),
+        };
+
+        let response = Response {
+            source: source.to_string(),
+            site_id,
+            path,
+        };
+        Some((fetched_at, response))
+    }
+}
+
+/// The name to record a response under, ex: 20201226T231047Z-sl-1011.json.
+pub fn file_name(
+    fetched_at: DateTime<Utc>,
+    source: &str,
+    site_id: Option<u32>,
+    extension: &str,
+) -> String {
+    let site = site_id.map(|site_id| format!("-{}", site_id)).unwrap_or_default();
+    format!("{}-{}{}.{}", fetched_at.format(TIMESTAMP_FORMAT), source, site, extension)
+}
+
+/// Parses a response recorded from `source` at `now`.
+pub fn parse(source: &str, body: &str, tz: Tz, now: DateTime<Utc>) -> Result<Board> {
+    match source {
+        "sl" => Ok(sl_v4::parse(body, tz, now)?),
+        "sl-transport" => Ok(sl_transport::parse(body, tz, now)?),
+        "siri" => Ok(siri::parse(body, now)?),
+        other => Err(format!("responses from {} can't be replayed", other).into()),
+    }
+}
+
+impl Recording {
+    pub fn open<P: AsRef<Path>>(dir: P) -> Result<Recording> {
+        let dir = dir.as_ref();
+        let mut responses = vec![];
+        for entry in std::fs::read_dir(dir)? {
+            let path = entry?.path();
+            // Responses that are still being written
+            if path.file_name().unwrap_or_default().to_string_lossy().starts_with('.') {
+                continue;
+            }
+            match Response::parse(path.clone()) {
+                Some(response) => responses.push(response),
+                None => eprintln!("Skipping {}, not a recorded response", path.display()),
+            }
+        }
+        responses.sort_by_key(|(fetched_at, response)| (*fetched_at, response.path.clone()));
+
+        let mut frames: Vec<Frame> = vec![];
+        for (fetched_at, response) in responses {
+            match frames.last_mut() {
+                Some(frame) if frame.fetched_at == fetched_at => frame.responses.push(response),
+                _ => frames.push(Frame {
+                    fetched_at,
+                    responses: vec![response],
+                }),
+            }
+        }
+
+        if frames.is_empty() {
+            return Err(format!("no recorded responses in {}", dir.display()).into());
+        }
+        Ok(Recording { frames })
+    }
+
+    /// The frames from the one that was current at `now` onwards, so that a
+    /// replay can pick up where it left off.
+    pub fn since(&self, now: DateTime<Utc>) -> Recording {
+        let start = self
+            .frames
+            .iter()
+            .rposition(|frame| frame.fetched_at <= now)
+            .unwrap_or(0);
+        Recording {
+            frames: self.frames[start..].to_vec(),
+        }
+    }
+}
+
+/// Departures from a recording, a frame per fetch. Times are read as of
+/// when the frame was recorded.
+pub struct ReplaySource {
+    frames: std::vec::IntoIter<Frame>,
+    tz: Tz,
+    /// Minutes, by site id
+    walk_times: HashMap<u32, u32>,
+}
+
+impl ReplaySource {
+    pub fn new(recording: Recording, walk_times: HashMap<u32, u32>, tz: Tz) -> ReplaySource {
+        ReplaySource {
+            frames: recording.frames.into_iter(),
+            tz,
+            walk_times,
+        }
+    }
+}
+
+impl DepartureSource for ReplaySource {
+    fn fetch(&mut self) -> Result<Board> {
+        let frame = match self.frames.next() {
+            Some(frame) => frame,
+            None => return Err("reached the end of the recording".into()),
+        };
+
+        let mut boards = vec![];
+        for response in &frame.responses {
+            let body = std::fs::read_to_string(&response.path)?;
+            let board = parse(&response.source, &body, self.tz, frame.fetched_at)?;
+            let walk_time = response
+                .site_id
+                .and_then(|site_id| self.walk_times.get(&site_id).copied())
+                .unwrap_or(0);
+            boards.push((board, walk_time));
+        }
+
+        Ok(merged::merge(boards))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_file_names() {
+        let (fetched_at, response) =
+            Response::parse("rec/20201226T231047Z-sl-transport-1011.json".into()).unwrap();
+        assert_eq!(fetched_at, Utc.ymd(2020, 12, 26).and_hms(23, 10, 47));
+        assert_eq!(response.source, "sl-transport");
+        assert_eq!(response.site_id, Some(1011));
+
+        let (_, response) = Response::parse("20201226T231047Z-siri.xml".into()).unwrap();
+        assert_eq!((response.source.as_str(), response.site_id), ("siri", None));
+
+        assert!(Response::parse("sl.json".into()).is_none());
+
+        let name = file_name(fetched_at, "sl-transport", Some(1011), "json");
+        assert_eq!(name, "20201226T231047Z-sl-transport-1011.json");
+    }
+
+    #[test]
+    fn replays_frames_in_order() {
+        let recording = Recording::open("./test/data/recording").unwrap();
+        assert_eq!(recording.frames.len(), 2);
+
+        let second = Utc.ymd(2020, 12, 26).and_hms(23, 12, 47);
+        assert_eq!(recording.since(second).frames.len(), 1);
+        assert_eq!(recording.since(second - chrono::Duration::seconds(1)).frames.len(), 2);
+
+        let walk_times = vec![(1011, 3)].into_iter().collect();
+        let mut source = ReplaySource::new(recording, walk_times, chrono_tz::Europe::Stockholm);
+        let first = source.fetch().unwrap();
+        assert_eq!(first.departures.len(), 7);
+        assert!(first.departures.iter().all(|departure| departure.walk_time == 3));
+
+        // Farsta strand is running late in the second frame
+        let later = source.fetch().unwrap();
+        let farsta = later.departures.last().unwrap();
+        assert_eq!(farsta.expected - farsta.scheduled, chrono::Duration::minutes(3));
+
+        assert!(source.fetch().is_err());
+    }
+}
@@ -0,0 +1,259 @@
+// SIRI is a family of XML services. We only read StopMonitoring, which lists
+// the vehicles calling at a stop, along with any SituationExchange messages
+// included in the same ServiceDelivery.
+
+use chrono::{prelude::*, Duration};
+use roxmltree::{Document, Node};
+
+use crate::{
+    departure::{Alert, Board, Departure, Deviation, TransportMode},
+    source::{read_feed, DepartureSource, FetchError},
+    Result,
+};
+
+/// Departures from a SIRI StopMonitoring service, given either as a URL or
+/// a saved response.
+pub struct SiriSource {
+    feed: String,
+}
+
+impl SiriSource {
+    pub fn new(feed: String) -> SiriSource {
+        SiriSource { feed }
+    }
+}
+
+impl DepartureSource for SiriSource {
+    fn fetch(&mut self) -> Result<Board> {
+        let body = String::from_utf8(read_feed(&self.feed)?)?;
+        Ok(parse(&body, Utc::now())?)
+    }
+}
+
+/// Finds the first child element called `name`, regardless of namespace.
+fn child<'a, 'input>(node: Node<'a, 'input>, name: &str) -> Option<Node<'a, 'input>> {
+    node.children().find(|x| x.tag_name().name() == name)
+}
+
+fn child_text<'a>(node: Node<'a, '_>, name: &str) -> Option<&'a str> {
+    child(node, name)
+        .and_then(|x| x.text())
+        .map(str::trim)
+        .filter(|x| !x.is_empty())
+}
+
+fn descendants<'a, 'input: 'a>(
+    node: Node<'a, 'input>,
+    name: &'a str,
+) -> impl Iterator<Item = Node<'a, 'input>> + 'a {
+    node.descendants().filter(move |x| x.tag_name().name() == name)
+}
+
+/// SIRI times carry their UTC offset, so there's no guessing the timezone.
+fn parse_time(time: &str) -> std::result::Result<DateTime<Utc>, FetchError> {
+    Ok(DateTime::parse_from_rfc3339(time)?.with_timezone(&Utc))
+}
+
+fn transport_mode(mode: Option<&str>) -> Option<TransportMode> {
+    match mode {
+        Some("metro") | Some("underground") => Some(TransportMode::Metro),
+        // Most StopMonitoring services leave out the mode, and most stops
+        // are bus stops
+        Some("bus") | Some("coach") | None => Some(TransportMode::Bus),
+        Some("rail") => Some(TransportMode::Train),
+        Some("tram") => Some(TransportMode::Tram),
+        Some("water") | Some("ferry") => Some(TransportMode::Ship),
+        _ => None,
+    }
+}
+
+fn importance(severity: Option<&str>) -> u32 {
+    match severity {
+        Some("verySevere") => 4,
+        Some("severe") => 3,
+        Some("normal") => 2,
+        _ => 1,
+    }
+}
+
+/// Parses a SIRI ServiceDelivery holding a StopMonitoringDelivery.
+pub fn parse(body: &str, now: DateTime<Utc>) -> std::result::Result<Board, FetchError> {
+    let document = Document::parse(body)?;
+    let root = document.root_element();
+
+    // Situations along with their numbers, so that departures can pick up
+    // the ones they refer to. The others concern the stop as a whole, and
+    // are shown in the order the service gives them.
+    let mut situations: Vec<(Option<&str>, Deviation)> = vec![];
+    for situation in descendants(root, "PtSituationElement") {
+        let text = match child_text(situation, "Summary") {
+            Some(text) => text,
+            None => continue,
+        };
+
+        situations.push((
+            child_text(situation, "SituationNumber"),
+            Deviation {
+                text: text.to_string(),
+                consequence: child_text(situation, "Description").map(str::to_string),
+                importance: importance(child_text(situation, "Severity")),
+            },
+        ));
+    }
+
+    let mut departures = vec![];
+    let mut referenced = vec![];
+    for visit in descendants(root, "MonitoredStopVisit") {
+        let journey = match child(visit, "MonitoredVehicleJourney") {
+            Some(journey) => journey,
+            None => continue,
+        };
+        let call = match child(journey, "MonitoredCall") {
+            Some(call) => call,
+            None => continue,
+        };
+        let transport_mode = match transport_mode(child_text(journey, "VehicleMode")) {
+            Some(mode) => mode,
+            None => continue,
+        };
+
+        // Calls at the last stop only have arrival times
+        let aimed = child_text(call, "AimedDepartureTime")
+            .or_else(|| child_text(call, "AimedArrivalTime"));
+        let scheduled = match aimed {
+            Some(aimed) => parse_time(aimed)?,
+            None => continue,
+        };
+        let expected = match child_text(call, "ExpectedDepartureTime")
+            .or_else(|| child_text(call, "ExpectedArrivalTime"))
+        {
+            Some(expected) => parse_time(expected)?,
+            None => scheduled,
+        };
+
+        let cancelled = child_text(journey, "Cancellation") == Some("true")
+            || child_text(call, "DepartureStatus") == Some("cancelled");
+
+        let situation_refs: Vec<&str> = descendants(visit, "SituationSimpleRef")
+            .filter_map(|x| x.text())
+            .map(str::trim)
+            .collect();
+        referenced.extend(situation_refs.iter().copied());
+
+        departures.push(Departure {
+            transport_mode,
+            line: child_text(journey, "PublishedLineName")
+                .or_else(|| child_text(journey, "LineRef"))
+                .unwrap_or_default()
+                .to_string(),
+            destination: child_text(call, "DestinationDisplay")
+                .or_else(|| child_text(journey, "DestinationName"))
+                .unwrap_or_default()
+                .to_string(),
+            direction: child_text(journey, "DirectionRef").and_then(|x| x.parse().ok()),
+            stop_area_name: child_text(call, "StopPointName")
+                .unwrap_or_default()
+                .to_string(),
+            stop_point_id: child_text(call, "StopPointRef")
+                .or_else(|| child_text(visit, "MonitoringRef"))
+                .unwrap_or_default()
+                .to_string(),
+            stop_point_designation: child_text(call, "DeparturePlatformName")
+                .or_else(|| child_text(call, "ArrivalPlatformName"))
+                .map(str::to_string),
+            scheduled,
+            expected,
+            journey_id: child(journey, "FramedVehicleJourneyRef")
+                .and_then(|x| child_text(x, "DatedVehicleJourneyRef"))
+                .or_else(|| child_text(journey, "DatedVehicleJourneyRef"))
+                .unwrap_or_default()
+                .to_string(),
+            cancelled,
+            walk_time: 0,
+            change: None,
+            deviations: situation_refs
+                .iter()
+                .filter_map(|x| {
+                    situations
+                        .iter()
+                        .find(|(number, _)| *number == Some(*x))
+                        .map(|(_, deviation)| deviation.clone())
+                })
+                .collect(),
+        });
+    }
+
+    let alerts = situations
+        .iter()
+        .filter(|(number, _)| number.is_none_or(|number| !referenced.contains(&number)))
+        .map(|(_, deviation)| Alert {
+            transport_mode: None,
+            deviation: deviation.clone(),
+        })
+        .collect();
+
+    let data_age = child(root, "ServiceDelivery")
+        .and_then(|x| child_text(x, "ResponseTimestamp"))
+        .and_then(|x| parse_time(x).ok())
+        .map(|produced| (now - produced).max(Duration::zero()).num_seconds() as u32)
+        .unwrap_or(0);
+
+    Ok(Board::new(departures, alerts, data_age))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_recorded_response() {
+        let body = std::fs::read_to_string("./test/data/siri.xml").unwrap();
+        let now = "2024-03-12T07:00:30Z".parse().unwrap();
+        let board = parse(&body, now).unwrap();
+
+        assert_eq!(board.departures.len(), 3);
+        assert_eq!(board.data_age, 30);
+
+        let first = &board.departures[0];
+        assert_eq!(first.transport_mode, TransportMode::Bus);
+        assert_eq!(first.line, "4");
+        assert_eq!(first.destination, "Radiohuset");
+        assert_eq!(first.stop_point_designation.as_deref(), Some("A"));
+        assert_eq!(first.expected - first.scheduled, Duration::minutes(3));
+        assert_eq!(first.deviations.len(), 1);
+        assert_eq!(first.deviations[0].importance, 3);
+
+        assert!(board.departures.iter().any(|x| x.cancelled));
+
+        assert_eq!(board.alerts.len(), 1);
+        assert_eq!(board.alerts[0].deviation.text, "Hissen är ur funktion");
+    }
+
+    #[test]
+    fn keeps_situations_in_order() {
+        let situation = |number: &str, summary: &str| {
+            format!(
+                "<PtSituationElement>{}<Summary>{}</Summary></PtSituationElement>",
+                number, summary
+            )
+        };
+        let body = format!(
+            "<Siri><ServiceDelivery><Situations>{}{}{}{}</Situations></ServiceDelivery></Siri>",
+            situation("", "Hissen är ur funktion"),
+            situation("<SituationNumber>2</SituationNumber>", "Omledning"),
+            situation("", "Rulltrappan är avstängd"),
+            situation("<SituationNumber>1</SituationNumber>", "Spårarbete"),
+        );
+        let board = parse(&body, Utc::now()).unwrap();
+
+        let alerts: Vec<_> = board
+            .alerts
+            .iter()
+            .map(|alert| alert.deviation.text.as_str())
+            .collect();
+        assert_eq!(
+            alerts,
+            vec!["Hissen är ur funktion", "Omledning", "Rulltrappan är avstängd", "Spårarbete"]
+        );
+    }
+}
@@ -0,0 +1,186 @@
+use chrono::{DateTime, Utc};
+use chrono_tz::Tz;
+
+use crate::{
+    departure::{Alert, Board, Departure, Deviation, TransportMode},
+    source::{http_get, DepartureSource, FetchError},
+    time,
+    trafiklab::transport,
+    Result,
+};
+
+/// Departures from SL's Transport API, which unlike realtimedeparturesV4
+/// doesn't need an API key.
+pub struct SlTransportSource {
+    site_id: u32,
+    tz: Tz,
+}
+
+impl SlTransportSource {
+    pub fn new(site_id: u32, tz: Tz) -> SlTransportSource {
+        SlTransportSource { site_id, tz }
+    }
+}
+
+impl DepartureSource for SlTransportSource {
+    fn fetch(&mut self) -> Result<Board> {
+        Ok(parse(&http_get(&url(self.site_id))?, self.tz, Utc::now())?)
+    }
+}
+
+/// Where to get the departures from `site_id` for the next hour.
+pub fn url(site_id: u32) -> String {
+    format!(
+        "https://transport.integration.sl.se/v1/sites/{}/departures?forecast=60",
+        site_id,
+    )
+}
+
+/// Parses a `/sites/{id}/departures` response body. Its times are wall clock
+/// times in `tz`, around `now`.
+pub fn parse(body: &str, tz: Tz, now: DateTime<Utc>) -> std::result::Result<Board, FetchError> {
+    let response: transport::DeparturesResponse = serde_json::from_str(body)?;
+
+    let departures = response
+        .departures
+        .into_iter()
+        .filter_map(|x| departure(x, tz, now))
+        .collect();
+
+    let alerts = response
+        .stop_deviations
+        .into_iter()
+        .map(|x| Alert {
+            transport_mode: None,
+            deviation: Deviation {
+                text: x.message,
+                consequence: None,
+                importance: x.importance_level,
+            },
+        })
+        .collect();
+
+    // The API doesn't say how old its data is, so assume it's fresh
+    Ok(Board::new(departures, alerts, 0))
+}
+
+fn transport_mode(mode: &str) -> Option<TransportMode> {
+    match mode {
+        "METRO" => Some(TransportMode::Metro),
+        "BUS" => Some(TransportMode::Bus),
+        "TRAIN" => Some(TransportMode::Train),
+        "TRAM" => Some(TransportMode::Tram),
+        "SHIP" | "FERRY" => Some(TransportMode::Ship),
+        _ => None,
+    }
+}
+
+/// Maps a departure onto our model, skipping modes we can't show such as
+/// replacement taxis.
+fn departure(departure: transport::Departure, tz: Tz, now: DateTime<Utc>) -> Option<Departure> {
+    let cancelled = departure.state == "CANCELLED" || departure.journey.state == "CANCELLED";
+
+    Some(Departure {
+        transport_mode: transport_mode(&departure.line.transport_mode)?,
+        line: departure.line.designation,
+        destination: departure.destination,
+        direction: Some(departure.direction_code),
+        stop_area_name: departure.stop_area.name,
+        stop_point_id: departure.stop_point.id.to_string(),
+        stop_point_designation: departure.stop_point.designation,
+        scheduled: time::from_local(tz, departure.scheduled, now),
+        expected: time::from_local(tz, departure.expected.unwrap_or(departure.scheduled), now),
+        journey_id: departure.journey.id.to_string(),
+        cancelled,
+        walk_time: 0,
+        change: None,
+        deviations: departure
+            .deviations
+            .into_iter()
+            .map(|x| Deviation {
+                text: x.message,
+                consequence: x.consequence,
+                importance: x.importance_level,
+            })
+            .collect(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+    use chrono_tz::Europe::Stockholm;
+
+    fn parse_fixture(path: &str, now: &str) -> Board {
+        let body = std::fs::read_to_string(path).unwrap();
+        parse(&body, Stockholm, now.parse().unwrap()).unwrap()
+    }
+
+    #[test]
+    fn parses_recorded_response() {
+        let board = parse_fixture("./test/data/sl_transport.json", "2024-03-12T07:00:00Z");
+
+        // The taxi departure is dropped, the rest are ordered by expected time
+        assert_eq!(board.departures.len(), 5);
+        assert!(board
+            .departures
+            .windows(2)
+            .all(|x| x[0].expected <= x[1].expected));
+
+        let first = &board.departures[0];
+        assert_eq!(first.transport_mode, TransportMode::Metro);
+        assert_eq!(first.line, "19");
+        assert_eq!(first.destination, "Hagsätra");
+        assert_eq!(first.stop_point_designation.as_deref(), Some("4"));
+        assert_eq!(first.journey_id, "2024031214759");
+
+        let cancelled: Vec<_> = board.departures.iter().filter(|x| x.cancelled).collect();
+        assert_eq!(cancelled.len(), 1);
+        assert_eq!(cancelled[0].expected, cancelled[0].scheduled);
+        assert_eq!(cancelled[0].deviations[0].consequence.as_deref(), Some("CANCELLED"));
+
+        assert_eq!(board.alerts.len(), 1);
+        assert_eq!(board.alerts[0].deviation.importance, 3);
+    }
+
+    #[test]
+    fn orders_departures_across_the_spring_dst_change() {
+        // Fetched at 01:50, ten minutes before clocks go forward to 03:00
+        let board = parse_fixture(
+            "./test/data/sl_transport_dst_spring.json",
+            "2024-03-31T00:50:00Z",
+        );
+        let expected: Vec<_> = board.departures.iter().map(|x| x.expected).collect();
+
+        assert_eq!(expected[0], "2024-03-31T00:55:00Z".parse::<DateTime<Utc>>().unwrap());
+        // 03:05 is only ten minutes after 01:55
+        assert_eq!(expected[1] - expected[0], Duration::minutes(10));
+        assert_eq!(expected[2] - expected[1], Duration::minutes(20));
+    }
+
+    #[test]
+    fn orders_departures_across_the_autumn_dst_change() {
+        // Fetched at 02:40 summer time, twenty minutes before clocks go back
+        // to 02:00
+        let board = parse_fixture(
+            "./test/data/sl_transport_dst_autumn.json",
+            "2024-10-27T00:40:00Z",
+        );
+        let departures: Vec<_> = board
+            .departures
+            .iter()
+            .map(|x| (x.line.as_str(), x.expected.to_rfc3339()))
+            .collect();
+
+        assert_eq!(
+            departures,
+            vec![
+                ("N1", "2024-10-27T00:50:00+00:00".to_string()),
+                // The 02:10 departure after clocks have gone back
+                ("N2", "2024-10-27T01:10:00+00:00".to_string()),
+                ("N1", "2024-10-27T01:20:00+00:00".to_string()),
+            ],
+        );
+    }
+}
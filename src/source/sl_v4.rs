@@ -0,0 +1,158 @@
+use chrono::{DateTime, Utc};
+use chrono_tz::Tz;
+
+use crate::{
+    departure::{Alert, Board, Departure, Deviation},
+    source::{http_get, DepartureSource, FetchError},
+    time, trafiklab, Result,
+};
+
+/// Departures from SL's realtimedeparturesV4 API, which needs an API key
+/// from Trafiklab.
+pub struct SlV4Source {
+    api_key: String,
+    site_id: u32,
+    tz: Tz,
+}
+
+impl SlV4Source {
+    pub fn new(api_key: String, site_id: u32, tz: Tz) -> SlV4Source {
+        SlV4Source {
+            api_key,
+            site_id,
+            tz,
+        }
+    }
+}
+
+impl DepartureSource for SlV4Source {
+    fn fetch(&mut self) -> Result<Board> {
+        let body = http_get(&url(&self.api_key, self.site_id))?;
+        Ok(parse(&body, self.tz, Utc::now())?)
+    }
+}
+
+/// Where to get the departures from `site_id` for the next hour.
+pub fn url(api_key: &str, site_id: u32) -> String {
+    format!(
+        "https://api.sl.se/api2/realtimedeparturesV4.json?key={}&siteid={}&timewindow=60",
+        api_key, site_id,
+    )
+}
+
+/// Parses a realtimedeparturesV4 response body. Its times are wall clock
+/// times in `tz`, around `now`.
+pub fn parse(body: &str, tz: Tz, now: DateTime<Utc>) -> std::result::Result<Board, FetchError> {
+    let response: trafiklab::RealtimeDeparturesV4Response = serde_json::from_str(body)?;
+    let data = match response {
+        trafiklab::RealtimeDeparturesV4Response {
+            status_code: 0,
+            response_data: Some(data),
+            ..
+        } => data,
+        trafiklab::RealtimeDeparturesV4Response { status_code, message, .. } => {
+            return Err(FetchError::Api {
+                status_code,
+                message: message.unwrap_or_default(),
+            });
+        }
+    };
+
+    let departures = data
+        .metros
+        .into_iter()
+        .chain(data.buses)
+        .chain(data.trains)
+        .chain(data.trams)
+        .chain(data.ships)
+        .map(|info| departure(info, tz, now))
+        .collect();
+
+    let alerts = data
+        .stop_point_deviations
+        .into_iter()
+        .map(|x| Alert {
+            transport_mode: Some(x.stop_info.transport_mode),
+            deviation: deviation(x.deviation),
+        })
+        .collect();
+
+    Ok(Board::new(departures, alerts, data.data_age))
+}
+
+fn departure(info: trafiklab::RealtimeDepartureInfo, tz: Tz, now: DateTime<Utc>) -> Departure {
+    Departure {
+        transport_mode: info.transport_mode,
+        line: info.line_number,
+        destination: info.destination,
+        direction: Some(info.journey_direction),
+        stop_area_name: info.stop_area_name,
+        stop_point_id: info.stop_point_number.to_string(),
+        stop_point_designation: info.stop_point_designation,
+        scheduled: time::from_local(tz, info.time_tabled_date_time, now),
+        expected: time::from_local(tz, info.expected_date_time, now),
+        journey_id: info.journey_number.to_string(),
+        cancelled: false,
+        walk_time: 0,
+        change: None,
+        deviations: info
+            .deviations
+            .unwrap_or_default()
+            .into_iter()
+            .map(deviation)
+            .collect(),
+    }
+}
+
+fn deviation(deviation: trafiklab::Deviation) -> Deviation {
+    Deviation {
+        text: deviation.text,
+        consequence: deviation.consequence,
+        importance: deviation.importance_level,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::departure::TransportMode;
+    use chrono::TimeZone;
+
+    #[test]
+    fn parses_recorded_response() {
+        let body = std::fs::read_to_string("./test/data/sl.json").unwrap();
+        let now = Utc.ymd(2020, 12, 26).and_hms(23, 10, 52);
+        let board = parse(&body, chrono_tz::Europe::Stockholm, now).unwrap();
+
+        assert_eq!(board.departures.len(), 76);
+        assert_eq!(board.data_age, 5);
+        assert_eq!(board.departures[0].line, "2");
+
+        let hagsatra = board
+            .departures
+            .iter()
+            .find(|departure| departure.journey_id == "14759")
+            .unwrap();
+        assert_eq!(hagsatra.transport_mode, TransportMode::Metro);
+        assert_eq!(hagsatra.destination, "Hagsätra");
+        assert_eq!(hagsatra.direction, Some(2));
+        assert_eq!(hagsatra.stop_point_id, "1012");
+        assert_eq!(hagsatra.stop_point_designation.as_deref(), Some("4"));
+        assert_eq!(hagsatra.scheduled, Utc.ymd(2020, 12, 26).and_hms(23, 12, 0));
+
+        assert_eq!(board.alerts.len(), 3);
+        assert_eq!(board.alerts[0].transport_mode, Some(TransportMode::Metro));
+        assert_eq!(board.alerts[0].deviation.importance, 2);
+    }
+
+    #[test]
+    fn reports_api_errors() {
+        let body = r#"{"StatusCode":1002,"Message":"Key is invalid","ResponseData":null}"#;
+        match parse(body, chrono_tz::Europe::Stockholm, Utc::now()) {
+            Err(FetchError::Api { status_code, message }) => {
+                assert_eq!((status_code, message.as_str()), (1002, "Key is invalid"));
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+}
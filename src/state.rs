@@ -0,0 +1,37 @@
+use std::path::Path;
+
+use chrono::{DateTime, Utc};
+use serde_derive::{Deserialize, Serialize};
+
+use crate::{departure::Board, Result};
+
+/// A successful fetch, along with when it happened.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Snapshot {
+    pub fetched_at: DateTime<Utc>,
+    pub board: Board,
+}
+
+impl Snapshot {
+    /// How old the departure data is at `now`. This includes the age the
+    /// data already had when the server sent it.
+    pub fn age(&self, now: DateTime<Utc>) -> chrono::Duration {
+        now - self.fetched_at + chrono::Duration::seconds(self.board.data_age.into())
+    }
+}
+
+pub fn load<P: AsRef<Path>>(path: P) -> Result<Snapshot> {
+    Ok(serde_json::from_str(&std::fs::read_to_string(path)?)?)
+}
+
+/// Writes the snapshot to a temporary file first and then moves it into
+/// place, so that losing power halfway through doesn't leave a truncated
+/// file behind.
+pub fn store<P: AsRef<Path>>(path: P, snapshot: &Snapshot) -> Result<()> {
+    let path = path.as_ref();
+    let tmp_path = path.with_extension("tmp");
+    std::fs::write(&tmp_path, serde_json::to_string(snapshot)?)?;
+    std::fs::rename(&tmp_path, path)?;
+
+    Ok(())
+}
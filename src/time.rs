@@ -0,0 +1,112 @@
+// Times are kept in UTC, so that comparisons hold across DST changes and
+// regardless of the system timezone, which is UTC on a stock Raspberry Pi.
+// APIs that give wall clock times without an offset are read in the
+// configured timezone.
+
+use chrono::{DateTime, Duration, LocalResult, NaiveDate, NaiveDateTime, TimeZone, Utc};
+use chrono_tz::Tz;
+
+/// Interprets a wall clock time in `tz`. When clocks are turned back the same
+/// wall clock time happens twice. Departures are listed from around
+/// `reference` onwards, so we go with the first one unless it's well before
+/// that. Times skipped when clocks are turned forward are read with the
+/// offset from before the change, i.e. 02:30 means 03:30.
+pub fn from_local(tz: Tz, time: NaiveDateTime, reference: DateTime<Utc>) -> DateTime<Utc> {
+    match tz.from_local_datetime(&time) {
+        LocalResult::Single(time) => time.with_timezone(&Utc),
+        LocalResult::Ambiguous(first, second) => {
+            let first = first.with_timezone(&Utc);
+            if first >= reference - Duration::minutes(10) {
+                first
+            } else {
+                second.with_timezone(&Utc)
+            }
+        }
+        LocalResult::None => match tz.from_local_datetime(&(time - Duration::hours(1))) {
+            LocalResult::Single(time) => time.with_timezone(&Utc) + Duration::hours(1),
+            _ => Utc.from_utc_datetime(&time),
+        },
+    }
+}
+
+/// The moment GTFS times on `date` are counted from, which is noon minus 12
+/// hours. That's midnight, except on days when clocks change.
+pub fn service_day_start(tz: Tz, date: NaiveDate) -> DateTime<Utc> {
+    let noon = tz
+        .from_local_datetime(&date.and_hms(12, 0, 0))
+        .earliest()
+        .map(|noon| noon.with_timezone(&Utc))
+        .unwrap_or_else(|| Utc.from_utc_datetime(&date.and_hms(12, 0, 0)));
+
+    noon - Duration::hours(12)
+}
+
+/// The calendar date in `tz` at `time`.
+pub fn local_date(tz: Tz, time: DateTime<Utc>) -> NaiveDate {
+    time.with_timezone(&tz).date().naive_local()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono_tz::Europe::Stockholm;
+
+    fn utc(time: &str) -> DateTime<Utc> {
+        time.parse().unwrap()
+    }
+
+    fn local(time: &str) -> NaiveDateTime {
+        time.parse().unwrap()
+    }
+
+    #[test]
+    fn reads_times_skipped_in_spring_with_the_old_offset() {
+        let reference = utc("2024-03-31T00:30:00Z");
+        assert_eq!(
+            from_local(Stockholm, local("2024-03-31T01:59:00"), reference),
+            utc("2024-03-31T00:59:00Z"),
+        );
+        assert_eq!(
+            from_local(Stockholm, local("2024-03-31T02:30:00"), reference),
+            utc("2024-03-31T01:30:00Z"),
+        );
+        assert_eq!(
+            from_local(Stockholm, local("2024-03-31T03:00:00"), reference),
+            utc("2024-03-31T01:00:00Z"),
+        );
+    }
+
+    #[test]
+    fn reads_the_repeated_autumn_hour_relative_to_the_reference() {
+        // Still in summer time, 02:30 is yet to come the first time around
+        let time = local("2024-10-27T02:30:00");
+        assert_eq!(
+            from_local(Stockholm, time, utc("2024-10-27T00:20:00Z")),
+            utc("2024-10-27T00:30:00Z"),
+        );
+        // At 02:40 summer time, 02:10 must mean the second time around
+        assert_eq!(
+            from_local(Stockholm, local("2024-10-27T02:10:00"), utc("2024-10-27T00:40:00Z")),
+            utc("2024-10-27T01:10:00Z"),
+        );
+        // Back in winter time, the first 02:30 is long gone
+        assert_eq!(
+            from_local(Stockholm, time, utc("2024-10-27T01:20:00Z")),
+            utc("2024-10-27T01:30:00Z"),
+        );
+    }
+
+    #[test]
+    fn counts_service_days_from_noon_minus_twelve_hours() {
+        let date = NaiveDate::from_ymd(2024, 3, 31);
+        assert_eq!(service_day_start(Stockholm, date), utc("2024-03-30T22:00:00Z"));
+
+        // 08:00 in the timetable is 08:00 on the clock, even though the day
+        // was an hour shorter
+        let eight = service_day_start(Stockholm, date) + Duration::hours(8);
+        assert_eq!(eight.with_timezone(&Stockholm).format("%H:%M").to_string(), "08:00");
+
+        let date = NaiveDate::from_ymd(2024, 10, 27);
+        assert_eq!(service_day_start(Stockholm, date), utc("2024-10-26T23:00:00Z"));
+    }
+}
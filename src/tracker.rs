@@ -0,0 +1,166 @@
+use std::collections::HashMap;
+
+use chrono::{DateTime, Duration, Utc};
+
+use crate::departure::{Board, Change, Departure};
+
+struct Journey {
+    first_expected: DateTime<Utc>,
+    added: bool,
+    departure: Departure,
+}
+
+/// Compares successive boards to notice departures that change in ways the
+/// provider doesn't report, so that rows don't silently move or disappear.
+pub struct JourneyTracker {
+    /// By journey and stop point, since a journey can call at several of the
+    /// stops on a merged board
+    journeys: HashMap<(String, String), Journey>,
+    /// How much later a departure must be expected before it counts as
+    /// delayed
+    delay_threshold: Duration,
+}
+
+impl JourneyTracker {
+    pub fn new(delay_threshold: Duration) -> JourneyTracker {
+        JourneyTracker {
+            journeys: HashMap::new(),
+            delay_threshold,
+        }
+    }
+
+    /// Annotates `board` with the changes since the previous one. Departures
+    /// that vanished are kept on the board until they would have left.
+    pub fn track(&mut self, board: Board, now: DateTime<Utc>) -> Board {
+        let Board {
+            departures,
+            alerts,
+            data_age,
+            scheduled_only,
+        } = board;
+
+        // Nothing to compare against on the first refresh
+        let first = self.journeys.is_empty();
+        let horizon = self.journeys.values().map(|x| x.departure.expected).max();
+
+        let mut seen = HashMap::new();
+        let mut tracked = vec![];
+        for mut departure in departures {
+            // Without a journey id there's nothing to recognize it by
+            if departure.journey_id.is_empty() {
+                tracked.push(departure);
+                continue;
+            }
+
+            let key = (departure.journey_id.clone(), departure.stop_point_id.clone());
+            let journey = match self.journeys.remove(&key) {
+                Some(journey) => journey,
+                None => Journey {
+                    first_expected: departure.expected,
+                    added: !first && horizon.is_some_and(|x| departure.expected < x),
+                    departure: departure.clone(),
+                },
+            };
+
+            let delay = departure.expected - journey.first_expected;
+            departure.change = if departure.cancelled {
+                None
+            } else if delay >= self.delay_threshold {
+                Some(Change::Delayed(delay.num_minutes()))
+            } else if journey.added {
+                Some(Change::Added)
+            } else {
+                None
+            };
+
+            tracked.push(departure.clone());
+            seen.insert(
+                key,
+                Journey {
+                    departure,
+                    ..journey
+                },
+            );
+        }
+
+        // Whatever is left wasn't in this board. Departures that have left
+        // or are about to are expected to drop off, the rest are suspicious.
+        for (key, mut journey) in self.journeys.drain() {
+            if journey.departure.cancelled
+                || journey.departure.expected < now + Duration::minutes(1)
+            {
+                continue;
+            }
+
+            journey.departure.change = Some(Change::Vanished);
+            tracked.push(journey.departure.clone());
+            seen.insert(key, journey);
+        }
+
+        self.journeys = seen;
+
+        let mut board = Board::new(tracked, alerts, data_age);
+        board.scheduled_only = scheduled_only;
+        board
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn departure(journey_id: &str, expected: DateTime<Utc>) -> Departure {
+        Departure {
+            scheduled: expected,
+            expected,
+            journey_id: journey_id.to_string(),
+            ..Departure::example()
+        }
+    }
+
+    fn changes(board: &Board) -> Vec<(&str, Option<Change>)> {
+        board
+            .departures
+            .iter()
+            .map(|departure| (departure.journey_id.as_str(), departure.change))
+            .collect()
+    }
+
+    #[test]
+    fn notices_changes_between_boards() {
+        let now = Utc::now();
+        let minutes = |minutes| now + Duration::minutes(minutes);
+        let mut tracker = JourneyTracker::new(Duration::minutes(3));
+
+        let board = Board::new(
+            vec![departure("a", minutes(5)), departure("b", minutes(10))],
+            vec![],
+            0,
+        );
+        assert_eq!(changes(&tracker.track(board, now)), vec![("a", None), ("b", None)]);
+
+        // b is pushed back, but not by enough to count as delayed
+        let board = Board::new(
+            vec![departure("a", minutes(5)), departure("b", minutes(12))],
+            vec![],
+            0,
+        );
+        assert_eq!(changes(&tracker.track(board, now)), vec![("a", None), ("b", None)]);
+
+        // a disappears before it has left, c turns up in between and b
+        // jumps ahead
+        let board = Board::new(
+            vec![departure("c", minutes(7)), departure("b", minutes(14))],
+            vec![],
+            0,
+        );
+        assert_eq!(
+            changes(&tracker.track(board, now)),
+            vec![
+                ("a", Some(Change::Vanished)),
+                ("c", Some(Change::Added)),
+                ("b", Some(Change::Delayed(4))),
+            ]
+        );
+    }
+}
@@ -0,0 +1,105 @@
+
+use chrono::prelude::*;
+use serde_derive::Deserialize;
+
+use crate::departure::TransportMode;
+
+pub mod nearby;
+pub mod transport;
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct RealtimeDepartureInfo {
+    /// ex: METRO
+    pub transport_mode: TransportMode,
+    /// ex: 19
+    pub line_number: String,
+    /// ex: Hagsätra
+    pub destination: String,
+    /// ex: 2
+    pub journey_direction: u32,
+    /// ex: Slussen
+    pub stop_area_name: String,
+    /// ex: 1012
+    pub stop_point_number: u32,
+    /// ex: 4
+    pub stop_point_designation: Option<String>,
+    /// ex: 2020-12-27T00:12:00
+    pub time_tabled_date_time: NaiveDateTime,
+    /// ex: 2020-12-27T00:12:00
+    pub expected_date_time: NaiveDateTime,
+    /// ex: 14759
+    pub journey_number: u32,
+    pub deviations: Option<Vec<Deviation>>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct Deviation {
+    /// ex: Håll avstånd - Res alltid med giltig biljett
+    pub text: String,
+    pub consequence: Option<String>,
+    /// ex: 5, higher is more important
+    pub importance_level: u32,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct StopInfo {
+    /// ex: METRO
+    pub transport_mode: TransportMode,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct StopPointDeviation {
+    pub stop_info: StopInfo,
+    pub deviation: Deviation,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct RealtimeDeparturesResponseData {
+    /// Seconds between when the departures were last updated and when the
+    /// response was generated, ex: 5
+    pub data_age: u32,
+    pub metros: Vec<RealtimeDepartureInfo>,
+    pub buses: Vec<RealtimeDepartureInfo>,
+    pub trains: Vec<RealtimeDepartureInfo>,
+    pub trams: Vec<RealtimeDepartureInfo>,
+    pub ships: Vec<RealtimeDepartureInfo>,
+    pub stop_point_deviations: Vec<StopPointDeviation>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct RealtimeDeparturesV4Response {
+    /// ex: 0, or 1002 when the key is invalid
+    pub status_code: u32,
+    /// ex: Key is invalid
+    pub message: Option<String>,
+    /// Missing whenever `status_code` is non-zero
+    pub response_data: Option<RealtimeDeparturesResponseData>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct SiteMatch {
+    /// ex: Slussen (Stockholm)
+    pub name: String,
+    /// ex: 9192
+    pub site_id: String,
+    /// ex: METRO, BUS. Missing for most stations
+    pub products: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct TypeaheadResponse {
+    /// ex: 0, or 1002 when the key is invalid
+    pub status_code: u32,
+    /// ex: Key is invalid
+    pub message: Option<String>,
+    /// Missing whenever `status_code` is non-zero
+    pub response_data: Option<Vec<SiteMatch>>,
+}
@@ -0,0 +1,44 @@
+// Responses from SL's nearbystopsv2 API. Only the parts we use are
+// modelled.
+
+use serde_derive::Deserialize;
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Product {
+    /// ex: Tunnelbana
+    pub cat_out_l: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StopLocation {
+    /// ex: Slussen (Stockholm)
+    pub name: String,
+    /// The site id with a 3001 prefix, ex: 300109192
+    pub ext_id: String,
+    /// Meters from the coordinates searched for, ex: 134
+    pub dist: Option<u32>,
+    /// One entry per line calling at the stop
+    #[serde(default)]
+    pub product_at_stop: Vec<Product>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Location {
+    /// Missing for entries that are addresses rather than stops
+    #[serde(rename = "StopLocation")]
+    pub stop_location: Option<StopLocation>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NearbyStopsResponse {
+    /// Missing when nothing is nearby
+    #[serde(default)]
+    pub stop_location_or_coord_location: Vec<Location>,
+    /// ex: API_AUTH, when the request failed
+    pub error_code: Option<String>,
+    /// ex: access denied for the given key
+    pub error_text: Option<String>,
+}
@@ -0,0 +1,80 @@
+// Responses from the SL Transport API, which unlike realtimedeparturesV4
+// needs no key. Only the parts we use are modelled.
+
+use chrono::prelude::*;
+use serde_derive::Deserialize;
+
+#[derive(Debug, Deserialize)]
+pub struct Departure {
+    /// ex: Hagsätra
+    pub destination: String,
+    /// ex: 2
+    pub direction_code: u32,
+    /// ex: EXPECTED, CANCELLED, NOTEXPECTED
+    pub state: String,
+    /// ex: 2024-03-12T08:02:00
+    pub scheduled: NaiveDateTime,
+    /// Missing when there's no prediction, e.g. for cancelled departures
+    pub expected: Option<NaiveDateTime>,
+    pub journey: Journey,
+    pub stop_area: StopArea,
+    pub stop_point: StopPoint,
+    pub line: Line,
+    #[serde(default)]
+    pub deviations: Vec<Deviation>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Journey {
+    /// ex: 2024031214759
+    pub id: u64,
+    /// ex: NORMALPROGRESS, CANCELLED
+    pub state: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct StopArea {
+    /// ex: Slussen
+    pub name: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct StopPoint {
+    /// ex: 1012
+    pub id: u32,
+    /// ex: 4
+    pub designation: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Line {
+    /// ex: 19
+    pub designation: String,
+    /// ex: METRO, BUS, TRAIN, TRAM, SHIP, FERRY
+    pub transport_mode: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Deviation {
+    /// ex: 5, higher is more important
+    pub importance_level: u32,
+    /// ex: CANCELLED, INFORMATION
+    pub consequence: Option<String>,
+    /// ex: Inställd
+    pub message: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct StopDeviation {
+    /// ex: 2, higher is more important
+    pub importance_level: u32,
+    /// ex: Hissen till plattformen är ur funktion.
+    pub message: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DeparturesResponse {
+    pub departures: Vec<Departure>,
+    #[serde(default)]
+    pub stop_deviations: Vec<StopDeviation>,
+}